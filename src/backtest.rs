@@ -0,0 +1,203 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+use crate::model::{ConnectionState, ExchangeCmd, Instrument, OrchestratorEvent, OrchestratorEvent::*, OrderType, PriceType, Side, State};
+use crate::orchestrator::process_event;
+use crate::recorder::RecordedFrame;
+use crate::replay::Replay;
+use crate::risk::SessionPnl;
+use crate::sim::{SimExchange, SimFill};
+
+
+/// Strategy reacting to events as a user would, by issuing user events, eg. Buy(Bid) or UpLastPrice.
+pub trait Algo {
+    fn on_event(&mut self, event: &OrchestratorEvent, state: &State) -> Vec<OrchestratorEvent>;
+}
+
+/// "Buy @ bid then chase": joins the touch once the feed is up, then on every touch move away, amends the order a tick
+/// at a time to rejoin it, till filled. One shot.
+#[derive(Debug)]
+pub struct Chase {
+    side: Side,
+    is_issued: bool,
+}
+
+impl Chase {
+    pub fn new(side: Side) -> Self {
+        Chase { side, is_issued: false }
+    }
+}
+
+impl Algo for Chase {
+    fn on_event(&mut self, event: &OrchestratorEvent, state: &State) -> Vec<OrchestratorEvent> {
+        let is_feed_up = state.connection == ConnectionState::Connected && state.bid > Decimal::ZERO && state.ask > Decimal::ZERO;
+        match (self.side, state.last_order(), event) {
+            (Side::Buy, None, _) if !self.is_issued && is_feed_up => {
+                self.is_issued = true;
+                vec!(Buy(PriceType::Bid))
+            }
+            (Side::Sell, None, _) if !self.is_issued && is_feed_up => {
+                self.is_issued = true;
+                vec!(Sell(PriceType::Ask))
+            }
+            // a tick per ack, as amends price off the acked order
+            (Side::Buy, Some(order), NewBid(_) | UpdateOrder(_)) if order.is_amendable() && order.price.is_some_and(|x| x < state.bid) => vec!(UpLastPrice),
+            (Side::Sell, Some(order), NewAsk(_) | UpdateOrder(_)) if order.is_amendable() && order.price.is_some_and(|x| x > state.ask) => vec!(DownLastPrice),
+            _ => vec!()
+        }
+    }
+}
+
+/// Fee rates on notional, negative for rebates.
+#[derive(Debug, Clone, Copy)]
+pub struct Fees {
+    pub maker_rate: Decimal,
+    pub taker_rate: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestFill {
+    pub fill: SimFill,
+    pub fee: Decimal,
+    pub slippage: Decimal,  // vs the touch when issued, in price, positive when adverse
+}
+
+/// Backtest results, with PnL and fees as per SessionPnl, ie. in the base currency for inverse contracts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub fills: Vec<BacktestFill>,
+    pub position_qty: Decimal,
+    pub realised_pnl: Decimal,
+    pub unrealised_pnl: Decimal,  // marked to the final mid
+    pub fees: Decimal,
+    pub net_pnl: Decimal,
+    pub max_drawdown: Decimal,    // of net PnL, peak to trough
+    pub avg_slippage: Decimal,    // qty weighted
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "FILLS: {}", self.fills.len())?;
+        for x in self.fills.iter() {
+            writeln!(f, "  {:<4} {:>10} @ {:>10.2} {:<5} fee {:>10.4} slippage {:>8.2}  {}",
+                x.fill.side.to_string(), x.fill.qty, x.fill.price, if x.fill.is_maker { "maker" } else { "taker" }, x.fee, x.slippage, x.fill.cl_ord_id)?;
+        }
+        writeln!(f, "POSITION: {}", self.position_qty)?;
+        writeln!(f, "REALISED PNL: {:.4} / UNREALISED PNL: {:.4}", self.realised_pnl, self.unrealised_pnl)?;
+        writeln!(f, "FEES: {:.4}", self.fees)?;
+        writeln!(f, "NET PNL: {:.4}", self.net_pnl)?;
+        writeln!(f, "MAX DRAWDOWN: {:.4}", self.max_drawdown)?;
+        write!(f, "AVG SLIPPAGE VS TOUCH: {:.4}", self.avg_slippage)
+    }
+}
+
+/// Parse a script of user actions, one "<RFC3339 time> <action>" per line, as per OrchestratorEvent::parse_action.
+/// Blank and # lines are ignored.
+pub fn parse_script(script: &str) -> Result<Vec<(DateTime<Utc>, OrchestratorEvent)>, String> {
+    script.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            let (at, action) = line.trim().split_once(char::is_whitespace).ok_or_else(|| format!("line {}: expected <time> <action>", i + 1))?;
+            let at = DateTime::parse_from_rfc3339(at).map_err(|err| format!("line {}: invalid time {}: {}", i + 1, at, err))?;
+            let event = OrchestratorEvent::parse_action(action.trim()).ok_or_else(|| format!("line {}: unknown action {}", i + 1, action.trim()))?;
+            Ok((at.with_timezone(&Utc), event))
+        })
+        .collect()
+}
+
+/// Run the replay, with its scripted events, and the optional algo through the orchestrator against SimExchange.
+pub fn run<I: Iterator<Item=io::Result<RecordedFrame>>>(replay: Replay<I>, state: &mut State, mut algo: Option<Box<dyn Algo>>, fees: Fees) -> io::Result<BacktestReport> {
+    let mut sim = SimExchange::new();
+    let mut touches: HashMap<String, Decimal> = HashMap::new();  // cl_ord_id -> bid/ask when issued
+    let mut ledger = Ledger::default();
+    let mut queue = VecDeque::from(vec!(NewConnectionState(ConnectionState::Connected)));
+    for step in replay {
+        queue.extend(step?.events);
+        while let Some(event) = queue.pop_front() {
+            queue.extend(sim.on_market_event(&event));
+            let (bid, ask) = (state.bid, state.ask);
+            if let Some(cmd) = process_event(&event, state) {
+                if let ExchangeCmd::IssueOrder(order) = &cmd {
+                    // the touch joined by passive limit orders, else the one crossed
+                    let is_passive = order.ord_type == Some(OrderType::Limit) && order.price.is_some_and(|x| if order.side == Some(Side::Buy) { x < ask } else { x > bid });
+                    let touch = match (order.side, is_passive) {
+                        (Some(Side::Buy), true) | (Some(Side::Sell), false) => bid,
+                        _ => ask
+                    };
+                    touches.insert(order.cl_ord_id.clone(), touch);
+                }
+                queue.extend(sim.handle_cmd(&cmd));
+            }
+            if let Some(algo) = algo.as_mut() {
+                queue.extend(algo.on_event(&event, state));
+            }
+        }
+        for fill in sim.fills()[ledger.fills.len()..].iter() {
+            ledger.add(fill, touches.get(&fill.cl_ord_id).copied().unwrap_or(fill.price), fees, state.instrument.as_ref());
+        }
+        ledger.mark((state.bid + state.ask) / Decimal::TWO, state.instrument.as_ref());
+    }
+    Ok(ledger.report())
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    fills: Vec<BacktestFill>,
+    pnl: SessionPnl,
+    unrealised_pnl: Decimal,
+    fees: Decimal,
+    peak_net_pnl: Decimal,
+    max_drawdown: Decimal,
+}
+
+impl Ledger {
+    fn add(&mut self, fill: &SimFill, touch: Decimal, fees: Fees, instrument: Option<&Instrument>) {
+        self.pnl.on_fill(fill.side, fill.qty, fill.price, instrument);
+        let fee = notional(fill.qty, fill.price, instrument) * if fill.is_maker { fees.maker_rate } else { fees.taker_rate };
+        self.fees += fee;
+        let slippage = if fill.side == Side::Buy { fill.price - touch } else { touch - fill.price };
+        self.fills.push(BacktestFill { fill: fill.clone(), fee, slippage });
+    }
+
+    fn mark(&mut self, mid: Decimal, instrument: Option<&Instrument>) {
+        if mid > Decimal::ZERO {
+            self.unrealised_pnl = self.pnl.unrealised_pnl(mid, instrument);
+        }
+        let net_pnl = self.net_pnl();
+        self.peak_net_pnl = self.peak_net_pnl.max(net_pnl);
+        self.max_drawdown = self.max_drawdown.max(self.peak_net_pnl - net_pnl);
+    }
+
+    fn net_pnl(&self) -> Decimal {
+        self.pnl.realised_pnl + self.unrealised_pnl - self.fees
+    }
+
+    fn report(self) -> BacktestReport {
+        let filled_qty = self.fills.iter().map(|x| x.fill.qty).sum::<Decimal>();
+        let avg_slippage = if filled_qty > Decimal::ZERO { self.fills.iter().map(|x| x.slippage * x.fill.qty).sum::<Decimal>() / filled_qty } else { Decimal::ZERO };
+        BacktestReport {
+            net_pnl: self.net_pnl(),
+            fills: self.fills,
+            position_qty: self.pnl.position_qty,
+            realised_pnl: self.pnl.realised_pnl,
+            unrealised_pnl: self.unrealised_pnl,
+            fees: self.fees,
+            max_drawdown: self.max_drawdown,
+            avg_slippage,
+        }
+    }
+}
+
+/// Notional in the currency of SessionPnl, ie. the base currency for inverse contracts.
+fn notional(qty: Decimal, price: Decimal, instrument: Option<&Instrument>) -> Decimal {
+    match instrument {
+        Some(x) if x.is_inverse => qty / price,
+        Some(x) => qty * price * x.multiplier,
+        None => qty * price
+    }
+}
@@ -1,5 +1,22 @@
-use clap::{App, AppSettings, Arg};
-use bitmex_warrior::sign;
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, stdin, stdout, Write};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use chrono::{DateTime, Utc};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use rust_decimal::Decimal;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+
+use bitmex_warrior::{backtest, refresh_ui, render, replay, sign};
+use bitmex_warrior::backtest::{Algo, Chase, Fees};
+use bitmex_warrior::model::{OrchestratorEvent, Side, State};
+use bitmex_warrior::recorder::{read_recording, recording_files, RecordedFrame};
+use bitmex_warrior::replay::{Replay, ReplayControl, ReplaySpeed};
 
 /// Playground for manual testing
 fn main() {
@@ -13,6 +30,22 @@ fn main() {
         )
         .subcommand(
             App::new("threads")
+        )
+        .subcommand(
+            with_state_args(App::new("replay")
+                .about("Replay recorded ws feed files, or directories of, through the orchestrator")
+                .arg(Arg::new("speed").short('s').long("speed").takes_value(true).default_value("realtime").about("realtime, max or eg. 10x"))
+                .arg(script_arg())
+                .arg(Arg::new("recordings").index(1).required(true).multiple(true)))
+        )
+        .subcommand(
+            with_state_args(App::new("backtest")
+                .about("Backtest scripted user actions and/or an algo against recorded ws feed files, or directories of")
+                .arg(script_arg())
+                .arg(Arg::new("algo").long("algo").takes_value(true).possible_values(&["chase-buy", "chase-sell"]))
+                .arg(Arg::new("maker-fee").long("maker-fee").takes_value(true).default_value("-0.00025"))
+                .arg(Arg::new("taker-fee").long("taker-fee").takes_value(true).default_value("0.00075"))
+                .arg(Arg::new("recordings").index(1).required(true).multiple(true)))
         );
     let arg_matches = app.get_matches();
 
@@ -20,11 +53,111 @@ fn main() {
         Some(("sign", sign_matches)) => {
             let api_secret = sign_matches.value_of("api-secret").unwrap();
             let to_be_signed = sign_matches.value_of("to-be-signed").unwrap();
-            println!("signed to-be-signed: {}, api_secrets: {} -> {}", to_be_signed, api_secret, sign::sign(&to_be_signed, &api_secret));
+            println!("signed to-be-signed: {}, api_secrets: {} -> {}", to_be_signed, api_secret, sign::sign(to_be_signed, api_secret));
         }
         Some(("threads", _threads_matches)) => {
             println!("...threads")
         }
+        Some(("replay", replay_matches)) => {
+            let speed = replay_matches.value_of("speed").and_then(ReplaySpeed::parse).expect("Invalid speed, expected realtime, max or eg. 10x");
+            let frames = read_recordings(replay_matches.values_of("recordings").unwrap()).expect("Failed to read recordings");
+            run_replay(Replay::new(frames, script_from_args(replay_matches)), state_from_args(replay_matches), speed).expect("Failed to replay");
+        }
+        Some(("backtest", backtest_matches)) => {
+            let algo: Option<Box<dyn Algo>> = match backtest_matches.value_of("algo") {
+                Some("chase-buy") => Some(Box::new(Chase::new(Side::Buy))),
+                Some("chase-sell") => Some(Box::new(Chase::new(Side::Sell))),
+                _ => None
+            };
+            let fee = |name: &str| backtest_matches.value_of(name).unwrap().parse::<Decimal>().unwrap_or_else(|_| panic!("Invalid {}", name));
+            let fees = Fees { maker_rate: fee("maker-fee"), taker_rate: fee("taker-fee") };
+            let frames = read_recordings(backtest_matches.values_of("recordings").unwrap()).expect("Failed to read recordings");
+            let mut state = state_from_args(backtest_matches);
+            let report = backtest::run(Replay::new(frames, script_from_args(backtest_matches)), &mut state, algo, fees).expect("Failed to backtest");
+            println!("{}", report);
+        }
         _ => unreachable!()  // thanks to AppSettings::SubcommandRequiredElseHelp
     };
 }
+
+/// Scripted user actions, as per backtest::parse_script.
+fn script_arg() -> Arg<'static> {
+    Arg::new("script").long("script").takes_value(true).about("file of \"<RFC3339 time> <action>\" lines, eg. \"2021-01-01T00:00:00Z buy-bid\"")
+}
+
+fn script_from_args(matches: &ArgMatches) -> Vec<(DateTime<Utc>, OrchestratorEvent)> {
+    matches.value_of("script")
+        .map(|x| fs::read_to_string(x).expect("Failed to read script"))
+        .map(|x| backtest::parse_script(&x).unwrap_or_else(|err| panic!("Invalid script: {}", err)))
+        .unwrap_or_default()
+}
+
+/// Instrument/order entry params for State.
+fn with_state_args(app: App) -> App {
+    app
+        .arg(Arg::new("qty").long("qty").takes_value(true).default_value("100"))
+        .arg(Arg::new("qty-inc").long("qty-inc").takes_value(true).default_value("10"))
+        .arg(Arg::new("lot-size").long("lot-size").takes_value(true).default_value("1"))
+        .arg(Arg::new("tick-size").long("tick-size").takes_value(true).default_value("0.5"))
+        .arg(Arg::new("trigger-offset").long("trigger-offset").takes_value(true).default_value("5"))
+}
+
+fn state_from_args(matches: &ArgMatches) -> State {
+    let arg = |name: &str| matches.value_of(name).unwrap().parse::<Decimal>().unwrap_or_else(|_| panic!("Invalid {}", name));
+    let mut state = State::new(arg("qty"), arg("qty-inc"), arg("tick-size"), arg("trigger-offset"));
+    state.lot_size = arg("lot-size");
+    state
+}
+
+/// Frames of recording files, and the recording files of directories, in order.
+fn read_recordings<'a>(paths: impl Iterator<Item=&'a str>) -> io::Result<impl Iterator<Item=io::Result<RecordedFrame>>> {
+    let mut files = Vec::new();
+    for path in paths.map(Path::new) {
+        if path.is_dir() {
+            files.extend(recording_files(path)?);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    let recordings = files.iter().map(|x| read_recording(x)).collect::<io::Result<Vec<_>>>()?;
+    Ok(recordings.into_iter().flatten())
+}
+
+/// Replay rendering the state, with space to pause/resume, n to step, +/- to double/halve the speed and q to quit.
+fn run_replay(replay: Replay<impl Iterator<Item=io::Result<RecordedFrame>>>, mut state: State, speed: ReplaySpeed) -> io::Result<()> {
+    let (control_tx, control_rx) = mpsc::channel();
+    let curr_speed = Arc::new(Mutex::new(speed));  // as last set, for the header
+    let key_speed = curr_speed.clone();
+    thread::spawn(move || {
+        for key in stdin().keys() {
+            let control = match key {
+                Ok(Key::Char(' ')) => ReplayControl::TogglePause,
+                Ok(Key::Char('n')) => ReplayControl::Step,
+                Ok(Key::Char(c @ ('+' | '-'))) => {
+                    let mut speed = key_speed.lock().unwrap();
+                    *speed = if c == '+' { speed.faster() } else { speed.slower() };
+                    ReplayControl::SetSpeed(*speed)
+                }
+                Ok(Key::Char('q')) | Ok(Key::Ctrl('c')) | Err(_) => ReplayControl::Stop,
+                _ => continue
+            };
+            if control_tx.send(control).is_err() || control == ReplayControl::Stop {
+                return
+            }
+        }
+    });
+
+    let mut stdout = stdout().into_raw_mode()?;
+    let cmd_count = Cell::new(0);
+    let res = replay::run(replay, &mut state, speed, &control_rx,
+        |cmd| {
+            cmd_count.set(cmd_count.get() + 1);
+            log::info!("Replay cmd: {:?}", cmd);
+        },
+        |state| {
+            let header = format!("REPLAY @ {:?}, space -> pause/resume, n -> step, +/- -> speed, q -> quit\r\nEXCHANGE CMDS: {}\r\n", curr_speed.lock().unwrap(), cmd_count.get());
+            refresh_ui!(stdout, render::render_state(&header, state));
+        });
+    write!(stdout, "\r\nReplay done, {} exchange cmds\r\n{}", cmd_count.get(), termion::cursor::Show)?;
+    res
+}
@@ -0,0 +1,262 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::model::{ExchangeCmd, ExchangeOrder, OrchestratorEvent, OrchestratorEvent::*, OrderStatus};
+use crate::recorder::RecordingConfig;
+use crate::rest::{self, RestError};
+use crate::sim::SimExchange;
+use crate::ws::{self, Heartbeat};
+
+
+/// Exchange backend driven by the orchestrator loop: order entry, cancel and amend, plus the market data subscription.
+#[async_trait]
+pub trait Exchange: Send {
+    async fn issue_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError>;
+
+    async fn cancel_orders(&mut self, cl_ord_ids: &[&str]) -> Result<Vec<OrchestratorEvent>, ExchangeError>;
+
+    /// Cancel all orders of the symbol, incl. those unknown to the orchestrator.
+    async fn cancel_all_orders(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError>;
+
+    /// Amend an order, with only the amended fields populated.
+    async fn amend_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError>;
+
+    /// Arm the dead man's switch, cancelling all orders unless re-armed within timeout, or disarm it on zero.
+    async fn cancel_all_after(&mut self, _timeout: Duration) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(Vec::new())
+    }
+
+    /// Contract specification of the traded instrument, if known to the exchange.
+    async fn fetch_instrument(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(Vec::new())
+    }
+
+    /// Push market data (and account updates) to tx from a background thread.
+    fn subscribe(&self, tx: mpsc::Sender<OrchestratorEvent>) -> Subscription;
+
+    /// Observe events passing through the orchestrator loop, eg. to match against market data.
+    fn on_event(&mut self, _event: &OrchestratorEvent) -> Vec<OrchestratorEvent> {
+        Vec::new()
+    }
+
+    /// Cancel all orders, then close the position via the execInst=Close order, even if the cancel fails. Concludes with
+    /// a status summarising both.
+    async fn cancel_all_and_close(&mut self, close_order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        let mut events = Vec::new();
+        let cancel_desc = match self.cancel_all_orders().await {
+            Ok(cancel_events) => {
+                let canceled_count = cancel_events.iter().filter(|x| matches!(x, UpdateOrder(o) if o.ord_status == OrderStatus::Canceled)).count();
+                events.extend(cancel_events);
+                format!("canceled {} orders", canceled_count)
+            }
+            Err(err) => format!("failed to cancel orders: {}", err)
+        };
+        let close_desc = match self.issue_order(close_order).await {
+            Ok(close_events) => {
+                let close_desc = close_events.iter().rev().find_map(|x| match x {  // as of the latest update
+                    UpdateOrder(o) => Some(format!("close order {}, {}", o.ord_status, o.fill_desc())),
+                    NewStatus(status) => Some(format!("failed to close position: {}", status)),
+                    _ => None
+                });
+                events.extend(close_events);
+                close_desc.unwrap_or_else(|| "close order issued".to_string())
+            }
+            Err(err) => format!("failed to close position: {}", err)
+        };
+        events.push(NewStatus(format!("Flatten all done: {}, {}", cancel_desc, close_desc)));
+        Ok(events)
+    }
+
+    async fn execute(&mut self, cmd: &ExchangeCmd<'_>) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        match cmd {
+            ExchangeCmd::IssueOrder(order) => self.issue_order(order).await,
+            ExchangeCmd::CancelOrder(cl_ord_id) => self.cancel_orders(&[cl_ord_id]).await,
+            ExchangeCmd::CancelOrders(cl_ord_ids) => self.cancel_orders(cl_ord_ids).await,
+            ExchangeCmd::CancelAllOrders => self.cancel_all_orders().await,
+            ExchangeCmd::AmendOrder(order) => self.amend_order(order).await,
+            ExchangeCmd::CancelAllAndClose(close_order) => self.cancel_all_and_close(close_order).await,
+            ExchangeCmd::CancelAllAfter(timeout) => self.cancel_all_after(*timeout).await,
+            ExchangeCmd::Batch(cmds) => {
+                // a failure mustn't hold up the rest, eg. a flattening order behind a failed cancel
+                let mut events = Vec::new();
+                for cmd in cmds.iter() {
+                    match self.execute(cmd).await {
+                        Ok(cmd_events) => events.extend(cmd_events),
+                        Err(err) => {
+                            log::error!("Failed to execute {:?}: {}", cmd, err);
+                            events.extend(failure_events(cmd, &err));
+                        }
+                    }
+                }
+                Ok(events)
+            }
+        }
+    }
+}
+
+
+/// Background threads pushing to the orchestrator, stoppable if stop_tx is present.
+#[derive(Debug)]
+pub struct Subscription {
+    threads: Vec<thread::JoinHandle<()>>,  // in stopping order
+    stop_tx: Option<mpsc::Sender<()>>,
+}
+
+impl From<thread::JoinHandle<()>> for Subscription {
+    fn from(thread: thread::JoinHandle<()>) -> Self {
+        Subscription { threads: vec!(thread), stop_tx: None }
+    }
+}
+
+impl Subscription {
+    pub fn stoppable(thread: thread::JoinHandle<()>, stop_tx: mpsc::Sender<()>) -> Self {
+        Subscription { threads: vec!(thread), stop_tx: Some(stop_tx) }
+    }
+
+    /// Add a thread that ends once the previous ones have.
+    pub fn and_then(mut self, thread: thread::JoinHandle<()>) -> Self {
+        self.threads.push(thread);
+        self
+    }
+
+    /// Wait for the threads to end of their own accord.
+    pub fn join(self) -> thread::Result<()> {
+        self.threads.into_iter().try_for_each(|x| x.join())
+    }
+
+    /// Signal the threads to stop, then wait for them, eg. for the ws recording to be finished.
+    pub fn stop(mut self) -> thread::Result<()> {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            stop_tx.send(()).ok();
+        }
+        self.join()
+    }
+}
+
+/// BitMEX, via the REST api and the /realtime WS.
+#[derive(Debug, Clone)]
+pub struct BitmexExchange {
+    pub http_url:      String,
+    pub wss_url:       String,
+    pub api_key:       String,
+    pub api_secret:    String,
+    pub symbol:        String,
+    pub subscriptions: Vec<String>,
+    pub heartbeat:     Heartbeat,
+    pub recording:     Option<RecordingConfig>,
+}
+
+#[async_trait]
+impl Exchange for BitmexExchange {
+    async fn issue_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(rest::issue_order(&self.http_url, &self.api_key, &self.api_secret, &self.symbol, order).await?)
+    }
+
+    async fn cancel_orders(&mut self, cl_ord_ids: &[&str]) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(rest::cancel_orders(&self.http_url, &self.api_key, &self.api_secret, cl_ord_ids).await?)
+    }
+
+    async fn cancel_all_orders(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(rest::cancel_all_orders(&self.http_url, &self.api_key, &self.api_secret, &self.symbol).await?)
+    }
+
+    async fn amend_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(vec!(rest::amend_order(&self.http_url, &self.api_key, &self.api_secret, order).await?))
+    }
+
+    async fn cancel_all_after(&mut self, timeout: Duration) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(vec!(rest::cancel_all_after(&self.http_url, &self.api_key, &self.api_secret, timeout).await?))
+    }
+
+    async fn fetch_instrument(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(vec!(rest::get_instrument(&self.http_url, &self.symbol).await?))
+    }
+
+    fn subscribe(&self, tx: mpsc::Sender<OrchestratorEvent>) -> Subscription {
+        let exchange = self.clone();
+        let (stop_tx, stop_rx) = mpsc::channel();
+        Subscription::stoppable(
+            thread::spawn(move || ws::handle_msgs(&exchange.wss_url, &exchange.api_key, &exchange.api_secret, exchange.subscriptions, exchange.heartbeat, exchange.recording, tx, stop_rx)),
+            stop_tx)
+    }
+}
+
+
+/// Paper trading, matching orders locally against the market data of another exchange.
+#[derive(Debug)]
+pub struct PaperExchange<E> {
+    pub sim: SimExchange,
+    pub market_data: E,
+}
+
+impl<E> PaperExchange<E> {
+    pub fn new(market_data: E) -> Self {
+        PaperExchange { sim: SimExchange::new(), market_data }
+    }
+}
+
+#[async_trait]
+impl<E: Exchange> Exchange for PaperExchange<E> {
+    async fn issue_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(self.sim.handle_cmd(&ExchangeCmd::IssueOrder(order.clone())))
+    }
+
+    async fn cancel_orders(&mut self, cl_ord_ids: &[&str]) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(self.sim.handle_cmd(&ExchangeCmd::CancelOrders(cl_ord_ids.to_vec())))
+    }
+
+    async fn cancel_all_orders(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(self.sim.cancel_all())
+    }
+
+    async fn amend_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(self.sim.handle_cmd(&ExchangeCmd::AmendOrder(order.clone())))
+    }
+
+    async fn fetch_instrument(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.market_data.fetch_instrument().await
+    }
+
+    /// Market data only, as live orders, fills and position would clobber the simulated ones.
+    fn subscribe(&self, tx: mpsc::Sender<OrchestratorEvent>) -> Subscription {
+        let (market_data_tx, market_data_rx) = mpsc::channel();
+        self.market_data.subscribe(market_data_tx).and_then(thread::spawn(move || {
+            for event in market_data_rx.iter().filter(|x| !is_private(x)) {
+                if tx.send(event).is_err() {
+                    return
+                }
+            }
+        }))
+    }
+
+    fn on_event(&mut self, event: &OrchestratorEvent) -> Vec<OrchestratorEvent> {
+        self.sim.on_market_event(event)
+    }
+}
+
+/// Outcome of a failed cmd: the order rejected, if a new one, and the failure as status.
+pub fn failure_events(cmd: &ExchangeCmd, err: &ExchangeError) -> Vec<OrchestratorEvent> {
+    let mut events = match cmd {
+        ExchangeCmd::IssueOrder(order) => vec!(UpdateOrder(ExchangeOrder::with_status(&order.cl_ord_id, OrderStatus::Rejected))),
+        _ => Vec::new()
+    };
+    events.push(NewStatus(format!("Failed to execute {:?}: {}", cmd, err)));
+    events
+}
+
+/// Account updates, as per the order, execution and position subscriptions.
+fn is_private(event: &OrchestratorEvent) -> bool {
+    matches!(event, UpdateOrder(_) | SyncLiveOrders(_) | NewFill(_) | UpdatePosition(_))
+}
+
+
+
+#[derive(Error, Debug)]
+pub enum ExchangeError {
+    #[error("rest error: {0}")]
+    RestError(#[from] RestError),
+}
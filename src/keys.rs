@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use termion::event::Key;
+use thiserror::Error;
+
+use crate::model::OrchestratorEvent;
+
+
+/// User actions, in USER_GUIDE order, with their descriptions. cancel-<n> is listed as cancel-n.
+const ACTIONS: [(&str, &str); 23] = [
+    ("buy-bid",                 "buy @ bid"),
+    ("sell-ask",                "sell @ ask"),
+    ("buy-ask",                 "buy @ ask"),
+    ("sell-bid",                "sell @ bid"),
+    ("up-qty",                  "up qty"),
+    ("down-qty",                "down qty"),
+    ("rotate-order-type",       "rotate order types"),
+    ("up-price",                "amend last order price up"),
+    ("down-price",              "amend last order price down"),
+    ("up-trigger-offset",       "up trigger offset"),
+    ("down-trigger-offset",     "down trigger offset"),
+    ("rotate-trigger-price",    "rotate trigger price type"),
+    ("rotate-time-in-force",    "rotate time in force"),
+    ("toggle-post-only",        "toggle post only"),
+    ("toggle-reduce-only",      "toggle reduce only"),
+    ("toggle-close-on-trigger", "toggle close on trigger"),
+    ("cancel-last",             "cancel last order"),
+    ("cancel-n",                "cancel nth order"),
+    ("cancel-all",              "cancel all orders"),
+    ("flatten-all",             "cancel all & close position"),
+    ("input",                   "input qty (q 5), price (p +2t)"),
+    ("unlock",                  "unlock after kill switch"),
+    ("exit",                    "exit"),
+];
+
+/// Bindings as of the original hard-coded keyboard map.
+const DEFAULT_BINDINGS: [(&str, &[&str]); 31] = [
+    ("buy-bid",                 &["z"]),
+    ("sell-ask",                &["x"]),
+    ("buy-ask",                 &["a"]),
+    ("sell-bid",                &["s"]),
+    ("up-qty",                  &["+", "="]),
+    ("down-qty",                &["-", "_"]),
+    ("rotate-order-type",       &["o"]),
+    ("up-price",                &["up"]),
+    ("down-price",              &["down"]),
+    ("up-trigger-offset",       &["]"]),
+    ("down-trigger-offset",     &["["]),
+    ("rotate-trigger-price",    &["t"]),
+    ("rotate-time-in-force",    &["f"]),
+    ("toggle-post-only",        &["p"]),
+    ("toggle-reduce-only",      &["r"]),
+    ("toggle-close-on-trigger", &["k"]),
+    ("cancel-last",             &["c"]),
+    ("cancel-1",                &["1"]),
+    ("cancel-2",                &["2"]),
+    ("cancel-3",                &["3"]),
+    ("cancel-4",                &["4"]),
+    ("cancel-5",                &["5"]),
+    ("cancel-6",                &["6"]),
+    ("cancel-7",                &["7"]),
+    ("cancel-8",                &["8"]),
+    ("cancel-9",                &["9"]),
+    ("cancel-all",              &["C"]),
+    ("flatten-all",             &["F"]),
+    ("input",                   &[":"]),
+    ("unlock",                  &["ctrl-u"]),
+    ("exit",                    &["ctrl-c"]),
+];
+
+const MIN_GUIDE_WIDTH: usize = 41;
+
+/// Validated mapping of keys, or chords of keys, to user actions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    bindings: Vec<(Vec<Key>, String, OrchestratorEvent)>,  // chord, key spec, event; in USER_GUIDE order
+}
+
+impl KeyBindings {
+    /// Parse action -> key specs, eg. "buy-bid" -> ["z", "ctrl-b", "alt-f1", "g b"], space separating chord keys.
+    /// Unknown actions or keys, keys bound more than once, keys prefixing chords, and no exit binding are rejected.
+    pub fn new(actions: &HashMap<String, Vec<String>>) -> Result<Self, KeyBindingError> {
+        let mut bindings = Vec::new();
+        for (action, key_specs) in actions.iter() {
+            let event = OrchestratorEvent::parse_action(action).ok_or_else(|| KeyBindingError::UnknownAction(action.clone()))?;
+            for key_spec in key_specs.iter() {
+                let chord = key_spec.split_whitespace().map(parse_key).collect::<Result<Vec<Key>, KeyBindingError>>()?;
+                if chord.is_empty() {
+                    return Err(KeyBindingError::UnknownKey(key_spec.clone()));
+                }
+                bindings.push((chord, key_spec.clone(), event.clone()));
+            }
+        }
+        bindings.sort_by_key(|(_, key_spec, event)| (action_rank(event), key_spec.clone()));
+
+        for (i, (chord1, key_spec1, _)) in bindings.iter().enumerate() {
+            for (chord2, key_spec2, _) in bindings[i + 1..].iter() {
+                if chord1 == chord2 {
+                    return Err(KeyBindingError::DuplicateBinding(key_spec1.clone()));
+                } else if chord2.starts_with(chord1) {
+                    return Err(KeyBindingError::ChordConflict(key_spec1.clone(), key_spec2.clone()));
+                } else if chord1.starts_with(chord2) {
+                    return Err(KeyBindingError::ChordConflict(key_spec2.clone(), key_spec1.clone()));
+                }
+            }
+        }
+        if !bindings.iter().any(|(_, _, event)| *event == OrchestratorEvent::Exit) {
+            return Err(KeyBindingError::MissingExit);
+        }
+        Ok(KeyBindings { bindings })
+    }
+
+    /// Bindings as configured, or the defaults.
+    pub fn from_config(actions: Option<&HashMap<String, Vec<String>>>) -> Result<Self, KeyBindingError> {
+        match actions {
+            Some(actions) => KeyBindings::new(actions),
+            None => KeyBindings::new(&DEFAULT_BINDINGS.iter().map(|(action, keys)| (action.to_string(), keys.iter().map(|x| x.to_string()).collect())).collect()),
+        }
+    }
+
+    /// Guide of the bindings, boxed, one line per action.
+    pub fn user_guide(&self) -> String {
+        let mut lines: Vec<(usize, String, Vec<&str>)> = Vec::new();  // rank, description, key specs
+        for (_, key_spec, event) in self.bindings.iter() {
+            let rank = action_rank(event);
+            match lines.last_mut() {
+                Some((last_rank, _, key_specs)) if *last_rank == rank => key_specs.push(key_spec),
+                _ => lines.push((rank, ACTIONS[rank].1.to_string(), vec!(key_spec))),
+            }
+        }
+        let lines = lines.iter().map(|(_, desc, key_specs)| format!("{} -> {}", describe_keys(key_specs), desc)).collect::<Vec<String>>();
+
+        let width = lines.iter().map(|x| x.chars().count() + 6).max().unwrap_or(0).max(MIN_GUIDE_WIDTH) | 1;  // odd, for the border pattern
+        let inner = width - 2;
+        let mut guide = format!(".{}-.\r\n", "-=".repeat((width - 3) / 2));
+        guide += &format!("|{}|\r\n", " ".repeat(inner));
+        let title = "BITMEX WARRIOR";
+        let right = (inner - title.len()) / 2;
+        guide += &format!("|{:>width$}{}|\r\n", title, " ".repeat(right), width = inner - right);
+        guide += &format!("|{}|\r\n", " ".repeat(inner));
+        for line in lines {
+            guide += &format!("|  {:<inner$}|\r\n", line, inner = inner - 2);
+        }
+        guide += &format!("|{}|\r\n", " ".repeat(inner));
+        guide += &format!("`{}-'\r\n", "-=".repeat((width - 3) / 2));
+        guide
+    }
+}
+
+/// Resolves keys into user actions, buffering the keys of partially typed chords, and of the input line whilst open.
+#[derive(Debug)]
+pub struct KeyMapper {
+    bindings: KeyBindings,
+    pending: Vec<Key>,
+    input: Option<String>,
+}
+
+impl KeyMapper {
+    pub fn new(bindings: KeyBindings) -> Self {
+        KeyMapper { bindings, pending: Vec::new(), input: None }
+    }
+
+    pub fn on_key(&mut self, key: Key) -> Vec<OrchestratorEvent> {
+        if let Some(input) = self.input.as_mut() {
+            match key {
+                Key::Char('\n') => {
+                    let line = self.input.take().unwrap();
+                    return match OrchestratorEvent::parse_input(&line) {
+                        _ if line.trim().is_empty() => vec!(OrchestratorEvent::EditInput(None)),
+                        Ok(event) => vec!(OrchestratorEvent::EditInput(None), event),
+                        Err(err) => vec!(OrchestratorEvent::EditInput(None), OrchestratorEvent::NewStatus(err)),
+                    };
+                }
+                Key::Esc => {
+                    self.input = None;
+                    return vec!(OrchestratorEvent::EditInput(None));
+                }
+                Key::Backspace => {
+                    input.pop();
+                    return vec!(OrchestratorEvent::EditInput(Some(input.clone())));
+                }
+                Key::Char(c) => {
+                    input.push(c);
+                    return vec!(OrchestratorEvent::EditInput(Some(input.clone())));
+                }
+                _ => return vec!()  // swallowed, eg. up would otherwise amend whilst typing
+            }
+        }
+
+        let event = self.resolve(key);
+        if let Some(OrchestratorEvent::EditInput(Some(input))) = &event {
+            self.input = Some(input.clone());
+        }
+        event.into_iter().collect()
+    }
+
+    fn resolve(&mut self, key: Key) -> Option<OrchestratorEvent> {
+        self.pending.push(key);
+        if let Some((_, _, event)) = self.bindings.bindings.iter().find(|(chord, _, _)| *chord == self.pending) {
+            self.pending.clear();
+            return Some(event.clone());
+        }
+        if self.bindings.bindings.iter().any(|(chord, _, _)| chord.starts_with(&self.pending)) {
+            return None;  // await the rest of the chord
+        }
+        // abandon the chord, the key may start afresh
+        let is_chord_abandoned = self.pending.len() > 1;
+        self.pending.clear();
+        if is_chord_abandoned { self.resolve(key) } else { None }
+    }
+}
+
+/// Key specs, comma separated, with runs of 3+ consecutive chars collapsed, eg. "1-9".
+fn describe_keys(key_specs: &[&str]) -> String {
+    let chars = key_specs.iter().map(|x| if x.chars().count() == 1 { x.chars().next() } else { None }).collect::<Option<Vec<char>>>();
+    match chars {
+        Some(mut chars) if chars.len() > 2 => {
+            chars.sort_unstable();
+            if chars.windows(2).all(|x| x[1] as u32 == x[0] as u32 + 1) {
+                return format!("{}-{}", chars[0], chars[chars.len() - 1]);
+            }
+            key_specs.join(", ")
+        }
+        _ => key_specs.join(", ")
+    }
+}
+
+fn action_rank(event: &OrchestratorEvent) -> usize {
+    let name = match event {
+        OrchestratorEvent::CancelAt(_) => "cancel-n",
+        _ => ACTIONS.iter().map(|(name, _)| *name).find(|x| OrchestratorEvent::parse_action(x).as_ref() == Some(event)).unwrap_or("exit"),
+    };
+    ACTIONS.iter().position(|(x, _)| *x == name).unwrap()
+}
+
+/// Parse a key, eg. "z", "C", "ctrl-c", "alt-x", "f5", "up", "space".
+fn parse_key(key_spec: &str) -> Result<Key, KeyBindingError> {
+    let single_char = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None
+        }
+    };
+    let key = match key_spec {
+        "up"        => Some(Key::Up),
+        "down"      => Some(Key::Down),
+        "left"      => Some(Key::Left),
+        "right"     => Some(Key::Right),
+        "home"      => Some(Key::Home),
+        "end"       => Some(Key::End),
+        "pageup"    => Some(Key::PageUp),
+        "pagedown"  => Some(Key::PageDown),
+        "insert"    => Some(Key::Insert),
+        "delete"    => Some(Key::Delete),
+        "backspace" => Some(Key::Backspace),
+        "backtab"   => Some(Key::BackTab),
+        "esc"       => Some(Key::Esc),
+        "space"     => Some(Key::Char(' ')),
+        "tab"       => Some(Key::Char('\t')),
+        "enter"     => Some(Key::Char('\n')),
+        _ => if let Some(c) = key_spec.strip_prefix("ctrl-").and_then(single_char) {
+            Some(Key::Ctrl(c))
+        } else if let Some(c) = key_spec.strip_prefix("alt-").and_then(single_char) {
+            Some(Key::Alt(c))
+        } else if let Some(n) = key_spec.strip_prefix('f').and_then(|x| x.parse::<u8>().ok()).filter(|x| (1..=12).contains(x)) {
+            Some(Key::F(n))
+        } else {
+            single_char(key_spec).map(Key::Char)
+        }
+    };
+    key.ok_or_else(|| KeyBindingError::UnknownKey(key_spec.to_string()))
+}
+
+
+#[derive(Error, Debug, PartialEq)]
+pub enum KeyBindingError {
+    #[error("unknown action {0}")]
+    UnknownAction(String),
+    #[error("unknown key {0}")]
+    UnknownKey(String),
+    #[error("key {0} bound more than once")]
+    DuplicateBinding(String),
+    #[error("key {0} prefixes chord {1}")]
+    ChordConflict(String, String),
+    #[error("no key bound to exit")]
+    MissingExit,
+}
@@ -1,14 +1,22 @@
 #[macro_use]
 extern crate enum_display_derive;
 
+pub mod backtest;
+pub mod exchange;
+pub mod keys;
 pub mod model;
 pub mod orchestrator;
+pub mod order_book;
+pub mod recorder;
 pub mod render;
+pub mod replay;
+pub mod risk;
 pub mod sign;
 pub mod ws;
 pub mod ws_model;
 pub mod rest;
 pub mod rest_model;
+pub mod sim;
 
 
 #[macro_export]
@@ -1,44 +1,21 @@
-mod orchestrator;
-mod model;
-mod render;
-mod sign;
-mod ws;
-mod ws_model;
-mod rest;
-mod rest_model;
-
-#[macro_use]
-extern crate enum_display_derive;
 #[macro_use]
 extern crate lazy_static;
 
+use std::env;
 use std::io::{stdin, stdout, Write};
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
 
-use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 
-use bitmex_warrior::{refresh_ui, show_cursor};
-use model::{*, OrchestratorEvent::*, PriceType::*};
+use bitmex_warrior::{orchestrator, refresh_ui, render, risk, show_cursor, ws};
+use bitmex_warrior::exchange::{BitmexExchange, Exchange, PaperExchange};
+use bitmex_warrior::keys::{KeyBindings, KeyMapper};
+use bitmex_warrior::model::{*, OrchestratorEvent::*};
 
 
-const USER_GUIDE: &str =
-".-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-.\r
-|                                       |\r
-|             BITMEX WARRIOR            |\r
-|                                       |\r
-|  z -> buy @ bid      x -> sell @ ask  |\r
-|  a -> buy @ ask      s -> sell @ bid  |\r
-|  + -> up qty         - -> down qty    |\r
-|  o -> rotate order types              |\r
-|  c -> cancel last order               |\r
-|  ctrl-c -> exit                       |\r
-|                                       |\r
-`-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-'\r
-";
-
 lazy_static! {
     static ref CFG: AppConfig = AppConfig::new("app").unwrap();
 }
@@ -52,7 +29,7 @@ lazy_static! {
 ///   - ctrl-s for sell @ bid
 /// 3. display current bid/ask as per WS feeds
 /// 4. list WebSocket events, perhaps in ncurses
-/// 5...∞ mutations of the above
+/// 5. ...∞ mutations of the above
 fn main() {
     log4rs::init_file("log4rs.yaml", Default::default()).unwrap();
     log::info!(r"
@@ -69,71 +46,68 @@ fn main() {
     ");
 
 
-    let (tx, rx) = mpsc::channel::<OrchestratorEvent>();
-    let tx2 = tx.clone();
-    let tx3 = tx.clone();
-    let orchestrator_thread = thread::spawn(move || {
-        let mut state = State::new(CFG.init_qty, CFG.qty_inc);
-        let mut stdout = stdout().into_raw_mode().unwrap();
-        refresh_ui!(stdout, USER_GUIDE);
-
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            loop {
-                match rx.recv() {
-                    Ok(Exit) => {
-                        println!();
-                        show_cursor!(stdout);
-                        break
-                    },
-                    Ok(e) => {
-                        if let Some(cmd) = orchestrator::process_event(&e, &mut state) {
-                            let rest_resp = match cmd {
-                                ExchangeCmd::CancelOrder(cl_ord_id) =>
-                                    rest::cancel_order(&CFG.http_url, &CFG.api_key, &CFG.api_secret, cl_ord_id).await,
-                                ExchangeCmd::IssueOrder(order) =>
-                                    rest::issue_order(&CFG.http_url, &CFG.api_key, &CFG.api_secret, &CFG.symbol.as_str(), &order).await
-                            };
-                            rest_resp.map(|x| tx3.send(x).expect("Failed to send event")).ok();
-                        };
-                        if state.has_refreshed {
-                            let rendered = render::render_state(USER_GUIDE, &state);
-                            refresh_ui!(stdout, rendered);
-                        }
-                    },
-                    Err(err) => {
-                        log::error!("mpsc channel receive error: {:?}", err);
-                        break
-                    }
-                }
-            }
-        })
-    });
+    let key_bindings = KeyBindings::from_config(CFG.keys.as_ref()).unwrap_or_else(|err| panic!("Invalid [keys] config: {}", err));
+    let user_guide = key_bindings.user_guide();
+    let mut key_mapper = KeyMapper::new(key_bindings);
 
-    let _ws_thread = thread::spawn(|| {
-        ws::handle_msgs(&CFG.wss_url, &CFG.api_key, &CFG.api_secret, CFG.wss_subscriptions.clone(), tx2);
-    });
+    let (tx, rx) = mpsc::channel::<OrchestratorEvent>();
+    let bitmex = BitmexExchange {
+        http_url:      CFG.http_url.clone(),
+        wss_url:       CFG.wss_url.clone(),
+        api_key:       CFG.api_key.clone(),
+        api_secret:    CFG.api_secret.clone(),
+        symbol:        CFG.symbol.clone(),
+        subscriptions: CFG.wss_subscriptions.clone(),
+        heartbeat:     ws::Heartbeat { interval: Duration::from_secs(CFG.heartbeat_interval_secs), timeout: Duration::from_secs(CFG.heartbeat_timeout_secs) },
+        recording:     CFG.recording.clone(),
+    };
+    let orchestrator_thread = if CFG.paper_trading || env::args().any(|x| x == "--paper") {
+        tx.send(NewStatus("Paper trading against live market data".to_string())).unwrap();
+        spawn_orchestrator(PaperExchange::new(bitmex), user_guide, None, rx, tx.clone())
+    } else {
+        spawn_orchestrator(bitmex, user_guide, CFG.dead_mans_switch.as_ref(), rx, tx.clone())
+    };
 
     let stdin = stdin();
     // http://ticki.github.io/blog/making-terminal-applications-in-rust-with-termion/
     for c in stdin.keys() {
-        let key = c.unwrap();
-        match key {
-            Key::Char('+') | Key::Char('=') => tx.send(UpQty).unwrap(),
-            Key::Char('-') | Key::Char('_') => tx.send(DownQty).unwrap(),
-            Key::Char('o') => tx.send(RotateOrderType).unwrap(),
-            Key::Char('z') => tx.send(Buy(Bid)).unwrap(),
-            Key::Char('x') => tx.send(Sell(Ask)).unwrap(),
-            Key::Char('a') => tx.send(Buy(Ask)).unwrap(),
-            Key::Char('s') => tx.send(Sell(Bid)).unwrap(),
-            Key::Char('c') => tx.send(CancelLast).unwrap(),
-            Key::Ctrl('c') => {
-                tx.send(Exit).unwrap();
-                break
-            },
-            _other => ()
+        let events = key_mapper.on_key(c.unwrap());
+        let is_exit = events.contains(&Exit);
+        for event in events {
+            tx.send(event).unwrap();
+        }
+        if is_exit {
+            break
         }
     }
 
     orchestrator_thread.join().unwrap();
+}
+
+/// Run the orchestrator loop against the exchange, rendering state changes, with the exchange's subscription feeding it,
+/// and the dead man's switch, if any, refreshed on a timer.
+fn spawn_orchestrator<E: Exchange + 'static>(mut exchange: E, user_guide: String, dead_mans_switch: Option<&'static DeadMansSwitchConfig>, rx: mpsc::Receiver<OrchestratorEvent>, tx: mpsc::Sender<OrchestratorEvent>) -> thread::JoinHandle<()> {
+    let subscription = exchange.subscribe(tx.clone());
+    let _dead_mans_switch_thread = dead_mans_switch.map(|x| orchestrator::spawn_dead_mans_switch_timer(Duration::from_secs(x.refresh_secs), tx.clone()));
+    thread::spawn(move || {
+        let mut state = State::new(CFG.init_qty, CFG.qty_inc, CFG.tick_size, CFG.trigger_offset);
+        state.tape_size = CFG.tape_size;
+        state.large_trade_size = CFG.large_trade_size;
+        state.lot_size = CFG.lot_size;
+        state.risk = risk::RiskEngine::new(CFG.risk.clone().unwrap_or_default());
+        state.dead_mans_switch = dead_mans_switch.map(|x| DeadMansSwitch { timeout: Duration::from_secs(x.timeout_secs), cancel_at: None });
+        let mut stdout = stdout().into_raw_mode().unwrap();
+        refresh_ui!(stdout, user_guide);
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(orchestrator::run(&mut exchange, &mut state, &rx, &tx, |state| {
+            let rendered = render::render_state(&user_guide, state);
+            refresh_ui!(stdout, rendered);
+        }));
+        if subscription.stop().is_err() {
+            log::error!("Subscription thread panicked");
+        }
+        println!();
+        show_cursor!(stdout);
+    })
 }
\ No newline at end of file
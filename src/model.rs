@@ -1,14 +1,77 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use config::{ConfigError, Config, File};
-use std::fmt::Display;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::time::Duration;
+use crate::recorder::RecordingConfig;
+use crate::risk::{RiskEngine, RiskLimits};
+
+const DEFAULT_TAPE_SIZE: usize = 50;
 
 
 #[derive(Serialize, Deserialize, Debug, Display, PartialEq, Clone, Copy)]
 pub enum OrderType {
     Limit,
-    Market
+    Market,
+    Stop,
+    StopLimit,
+    MarketIfTouched,
+    LimitIfTouched
+}
+const ALL_ORDER_TYPES: &[OrderType] = &[OrderType::Limit, OrderType::Market, OrderType::Stop, OrderType::StopLimit, OrderType::MarketIfTouched, OrderType::LimitIfTouched];
+
+impl OrderType {
+    /// Orders resting on the book at a limit price.
+    pub fn has_limit_price(self) -> bool {
+        matches!(self, OrderType::Limit | OrderType::StopLimit | OrderType::LimitIfTouched)
+    }
+
+    /// Orders dormant till the stopPx trigger is hit.
+    pub fn is_conditional(self) -> bool {
+        matches!(self, OrderType::Stop | OrderType::StopLimit | OrderType::MarketIfTouched | OrderType::LimitIfTouched)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Display, PartialEq, Clone, Copy)]
+pub enum TimeInForce {
+    GoodTillCancel,
+    ImmediateOrCancel,
+    FillOrKill,
+    Day
+}
+const ALL_TIME_IN_FORCES: &[TimeInForce] = &[TimeInForce::GoodTillCancel, TimeInForce::ImmediateOrCancel, TimeInForce::FillOrKill, TimeInForce::Day];
+
+#[derive(Serialize, Deserialize, Debug, Display, PartialEq, Clone, Copy)]
+pub enum ExecInst {
+    ParticipateDoNotInitiate,
+    ReduceOnly,
+    Close,
+    MarkPrice,
+    LastPrice,
+    IndexPrice
+}
+const ALL_TRIGGER_PRICES: &[ExecInst] = &[ExecInst::LastPrice, ExecInst::MarkPrice, ExecInst::IndexPrice];
+
+impl ExecInst {
+    /// Parse BitMEX's comma separated execInst, ignoring instructions not supported here.
+    pub fn parse_list(s: &str) -> Vec<ExecInst> {
+        s.split(',').filter_map(|x| match x.trim() {
+            "ParticipateDoNotInitiate" => Some(ExecInst::ParticipateDoNotInitiate),
+            "ReduceOnly" => Some(ExecInst::ReduceOnly),
+            "Close"      => Some(ExecInst::Close),
+            "MarkPrice"  => Some(ExecInst::MarkPrice),
+            "LastPrice"  => Some(ExecInst::LastPrice),
+            "IndexPrice" => Some(ExecInst::IndexPrice),
+            _ => None
+        }).collect()
+    }
+
+    pub fn to_list_string(exec_insts: &[ExecInst]) -> String {
+        exec_insts.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",")
+    }
 }
-const ALL_ORDER_TYPES: &[OrderType] = &[OrderType::Limit, OrderType::Market];
 
 #[derive(Serialize, Deserialize, Debug, Display, PartialEq, Clone, Copy)]
 pub enum OrderStatus {
@@ -38,47 +101,367 @@ pub struct ExchangeOrder {
     pub cl_ord_id: String,
     pub ord_status: OrderStatus,
     pub ord_type: Option<OrderType>,
-    pub price: Option<f64>,
-    pub qty: Option<f64>,
+    pub price: Option<Decimal>,
+    pub qty: Option<Decimal>,
     pub side: Option<Side>,
+    pub cum_qty: Option<Decimal>,
+    pub leaves_qty: Option<Decimal>,
+    pub avg_px: Option<Decimal>,
+    pub stop_px: Option<Decimal>,
+    pub time_in_force: Option<TimeInForce>,
+    pub exec_inst: Option<Vec<ExecInst>>,
 }
 
-#[derive(Debug, PartialEq)]
+impl ExchangeOrder {
+    /// Update of just the status, eg. of an order rejected on issue.
+    pub fn with_status(cl_ord_id: &str, ord_status: OrderStatus) -> Self {
+        ExchangeOrder { cl_ord_id: cl_ord_id.to_string(), ord_status, ord_type: None, price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }
+    }
+
+    pub fn is_cancelable(&self) -> bool {
+        matches!(self.ord_status, OrderStatus::New | OrderStatus::NotYetIssued | OrderStatus::PartiallyFilled)
+    }
+
+    /// Only orders acknowledged by the exchange can be amended.
+    pub fn is_amendable(&self) -> bool {
+        matches!(self.ord_status, OrderStatus::New | OrderStatus::PartiallyFilled) && self.ord_type == Some(OrderType::Limit) && self.price.is_some()
+    }
+
+    /// Fill progress, eg. "filled 40/100 @ avg 51234.5".
+    pub fn fill_desc(&self) -> String {
+        format!("filled {}/{} @ avg {}", self.cum_qty.unwrap_or(Decimal::ZERO), self.qty.map_or_else(|| "?".to_string(), |x| x.to_string()), self.avg_px.map_or_else(|| "-".to_string(), |x| x.to_string()))
+    }
+}
+
+/// Order book price level.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Level {
+    pub price: Decimal,
+    pub size: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]  // variants mirror BitMEX naming, rendered via symbol()
+pub enum TickDirection {
+    ZeroPlusTick,
+    PlusTick,
+    ZeroMinusTick,
+    MinusTick,
+}
+
+impl TickDirection {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            TickDirection::PlusTick      => "+",
+            TickDirection::ZeroPlusTick  => "0+",
+            TickDirection::MinusTick     => "-",
+            TickDirection::ZeroMinusTick => "0-",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trade {
+    pub timestamp: DateTime<Utc>,
+    pub side: Side,
+    pub size: Decimal,
+    pub price: Decimal,
+    pub tick_direction: TickDirection,
+}
+
+#[derive(Debug, Display, PartialEq, Clone, Copy)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Stale,  // connected, but silent for longer than the heartbeat interval
+    Disconnected
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Fill {
+    pub cl_ord_id: String,
+    pub exec_id: String,
+    pub side: Side,
+    pub last_qty: Decimal,
+    pub last_px: Decimal,
+    pub cum_qty: Option<Decimal>,  // as reported by the exchange, post this fill
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Position {
+    pub current_qty: Option<Decimal>,
+    pub avg_entry_price: Option<Decimal>,
+    pub liquidation_price: Option<Decimal>,
+    pub unrealised_pnl: Option<Decimal>,
+    pub realised_pnl: Option<Decimal>,
+    pub leverage: Option<Decimal>,
+    pub mark_price: Option<Decimal>,
+}
+
+impl Position {
+    /// Overlay the fields present in the update, as the position table only sends changed fields.
+    pub fn merge(&self, update: &Position) -> Position {
+        Position {
+            current_qty:       update.current_qty.or(self.current_qty),
+            avg_entry_price:   update.avg_entry_price.or(self.avg_entry_price),
+            liquidation_price: update.liquidation_price.or(self.liquidation_price),
+            unrealised_pnl:    update.unrealised_pnl.or(self.unrealised_pnl),
+            realised_pnl:      update.realised_pnl.or(self.realised_pnl),
+            leverage:          update.leverage.or(self.leverage),
+            mark_price:        update.mark_price.or(self.mark_price),
+        }
+    }
+}
+
+/// Contract specification, as per /api/v1/instrument.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instrument {
+    pub symbol: String,
+    pub tick_size: Decimal,
+    pub lot_size: Decimal,
+    pub multiplier: Decimal,  // of contract value, negative for inverse contracts
+    pub is_inverse: bool,
+    pub max_order_qty: Option<Decimal>,
+}
+
+/// Dead man's switch, ie. cancelAllAfter, kept armed whilst the feed is healthy.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DeadMansSwitch {
+    pub timeout: Duration,
+    pub cancel_at: Option<DateTime<Utc>>,  // as per the exchange, None whilst disarmed
+}
+
+/// Round to the nearest multiple of step, midpoints away from zero, sans trailing zeros.
+pub fn round_to_step(x: Decimal, step: Decimal) -> Decimal {
+    ((x / step).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero) * step).normalize()
+}
+
+/// Limit price as entered on the input line, applied to the touch of subsequent orders.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PriceInput {
+    Absolute(Decimal),
+    Ticks(i64),    // offset from the touch
+    Percent(Decimal),  // offset from the touch
+}
+
+impl PriceInput {
+    /// Parse eg. "50123.5", "+2t", "-0.1%".
+    pub fn parse(s: &str) -> Option<PriceInput> {
+        let is_offset = s.starts_with('+') || s.starts_with('-');
+        if let Some(ticks) = s.strip_suffix('t').filter(|_| is_offset) {
+            ticks.parse::<i64>().ok().map(PriceInput::Ticks)
+        } else if let Some(pct) = s.strip_suffix('%').filter(|_| is_offset) {
+            pct.parse::<Decimal>().ok().map(PriceInput::Percent)
+        } else if !is_offset {
+            s.parse::<Decimal>().ok().map(PriceInput::Absolute)
+        } else {
+            None
+        }
+    }
+
+    /// Price for the touch, percentage offsets rounded to the nearest tick.
+    pub fn apply(self, touch: Decimal, tick_size: Decimal) -> Decimal {
+        match self {
+            PriceInput::Absolute(price) => price,
+            PriceInput::Ticks(ticks) => touch + Decimal::from(ticks) * tick_size,
+            PriceInput::Percent(pct) => round_to_step(touch * (Decimal::ONE + pct / Decimal::ONE_HUNDRED), tick_size),
+        }
+    }
+}
+
+impl Display for PriceInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PriceInput::Absolute(price) => write!(f, "{}", price),
+            PriceInput::Ticks(ticks) => write!(f, "{:+}t", ticks),
+            PriceInput::Percent(pct) => write!(f, "{:+}%", pct),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum OrchestratorEvent {
     Buy(PriceType),  // from user
     Sell(PriceType), // from user
     CancelLast,      // from user
+    CancelAt(usize), // from user, 1-based position of the live order, as rendered
+    CancelAll,       // from user
+    FlattenAll,      // from user, cancels all orders of the symbol and closes the position, regardless of State.orders
     UpQty,           // from user
     DownQty,         // from user
+    UpLastPrice,     // from user, amends last order's price by a tick
+    DownLastPrice,   // from user, amends last order's price by a tick
+    UpTriggerOffset,   // from user
+    DownTriggerOffset, // from user
     RotateOrderType, // from user
-    NewBid(f64),     // from WS
-    NewAsk(f64),     // from WS
+    RotateTimeInForce,    // from user
+    RotateTriggerPrice,   // from user
+    TogglePostOnly,       // from user
+    ToggleReduceOnly,     // from user
+    ToggleCloseOnTrigger, // from user
+    EditInput(Option<String>),    // from user, the input line as typed, None once closed
+    SetQty(Decimal),                  // from user, via the input line
+    SetPrice(Option<PriceInput>), // from user, via the input line, None for the touch
+    Unlock,          // from user, lifts the kill switch lockout
+    NewBid(Decimal),     // from WS
+    NewAsk(Decimal),     // from WS
+    NewDepth(Vec<Level>, Vec<Level>),  // from WS, bids and asks, best first
+    NewTrades(Vec<Trade>),             // from WS
     NewStatus(String),  // from WS
     UpdateOrder(ExchangeOrder),  // from WS/Rest
+    UpdatePosition(Position),    // from WS
+    NewFill(Fill),               // from WS
+    SyncLiveOrders(Vec<String>), // from WS, cl_ord_id's of all open orders, as per snapshot
+    NewConnectionState(ConnectionState),  // from WS
+    NewInstrument(Instrument),   // from Rest
+    RefreshDeadMansSwitch,       // from timer
+    UpdateDeadMansSwitch(Option<DateTime<Utc>>),  // from Rest, cancel time, None once disarmed
     Exit             // from user
 }
 
+impl OrchestratorEvent {
+    /// Parse a user action by name, eg. "buy-bid", "cancel-3", as used in scripts and key bindings.
+    pub fn parse_action(name: &str) -> Option<OrchestratorEvent> {
+        let event = match name {
+            "buy-bid"                 => OrchestratorEvent::Buy(PriceType::Bid),
+            "buy-ask"                 => OrchestratorEvent::Buy(PriceType::Ask),
+            "sell-bid"                => OrchestratorEvent::Sell(PriceType::Bid),
+            "sell-ask"                => OrchestratorEvent::Sell(PriceType::Ask),
+            "cancel-last"             => OrchestratorEvent::CancelLast,
+            "cancel-all"              => OrchestratorEvent::CancelAll,
+            "flatten-all"             => OrchestratorEvent::FlattenAll,
+            "up-qty"                  => OrchestratorEvent::UpQty,
+            "down-qty"                => OrchestratorEvent::DownQty,
+            "up-price"                => OrchestratorEvent::UpLastPrice,
+            "down-price"              => OrchestratorEvent::DownLastPrice,
+            "up-trigger-offset"       => OrchestratorEvent::UpTriggerOffset,
+            "down-trigger-offset"     => OrchestratorEvent::DownTriggerOffset,
+            "rotate-order-type"       => OrchestratorEvent::RotateOrderType,
+            "rotate-time-in-force"    => OrchestratorEvent::RotateTimeInForce,
+            "rotate-trigger-price"    => OrchestratorEvent::RotateTriggerPrice,
+            "toggle-post-only"        => OrchestratorEvent::TogglePostOnly,
+            "toggle-reduce-only"      => OrchestratorEvent::ToggleReduceOnly,
+            "toggle-close-on-trigger" => OrchestratorEvent::ToggleCloseOnTrigger,
+            "input"                   => OrchestratorEvent::EditInput(Some("".to_string())),
+            "unlock"                  => OrchestratorEvent::Unlock,
+            "exit"                    => OrchestratorEvent::Exit,
+            _ => return name.strip_prefix("cancel-").and_then(|x| x.parse::<usize>().ok()).filter(|x| *x > 0).map(OrchestratorEvent::CancelAt)
+        };
+        Some(event)
+    }
+
+    /// Parse an input line, ie. "q <qty>" or "p <price>", where price is absolute or an offset from the touch, eg. "+2t"
+    /// or "-0.1%", or empty to revert to the touch.
+    pub fn parse_input(line: &str) -> Result<OrchestratorEvent, String> {
+        let mut tokens = line.split_whitespace();
+        match (tokens.next(), tokens.next(), tokens.next()) {
+            (Some("q"), Some(qty), None) => qty.parse::<Decimal>().ok().map(OrchestratorEvent::SetQty).ok_or_else(|| format!("Invalid qty: {}", qty)),
+            (Some("p"), None, None) => Ok(OrchestratorEvent::SetPrice(None)),
+            (Some("p"), Some(price), None) => PriceInput::parse(price).map(|x| OrchestratorEvent::SetPrice(Some(x))).ok_or_else(|| format!("Invalid price: {}", price)),
+            _ => Err(format!("Invalid input: {}, expected q <qty> or p <price|+Nt|-N%>", line.trim()))
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ExchangeCmd<'a> {
     IssueOrder(ExchangeOrder),
-    CancelOrder(&'a str)
+    CancelOrder(&'a str),
+    CancelOrders(Vec<&'a str>),
+    CancelAllOrders,  // all orders of the symbol, incl. those unknown to State
+    AmendOrder(ExchangeOrder),  // only the fields to be amended are populated
+    CancelAllAndClose(ExchangeOrder),  // all orders of the symbol, then the position via the execInst=Close order
+    CancelAllAfter(Duration),  // dead man's switch, zero disarming it
+    Batch(Vec<ExchangeCmd<'a>>)  // executed in order, carrying on past failures, eg. cancels ahead of a flattening order
 }
 
 #[derive(Debug, PartialEq)]
 pub struct State {
-    pub bid: f64,
-    pub ask: f64,
-    pub qty: f64,
-    pub qty_increment: f64,
-    pub order: Option<ExchangeOrder>,
+    pub bid: Decimal,
+    pub ask: Decimal,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    pub recent_trades: VecDeque<Trade>,  // most recent first
+    pub tape_size: usize,                // max recent trades retained
+    pub large_trade_size: Decimal,           // trades of at least this size are highlighted
+    pub qty: Decimal,
+    pub qty_increment: Decimal,
+    pub lot_size: Decimal,   // orders' qty must be a multiple of
+    pub tick_size: Decimal,
+    pub instrument: Option<Instrument>,   // once fetched, source of the tick and lot sizes
+    pub price_input: Option<PriceInput>,  // limit price as entered, else the touch
+    pub trigger_offset: Decimal,  // distance of conditional orders' stopPx from bid/ask
+    pub orders: HashMap<String, ExchangeOrder>,
+    pub order_ids: Vec<String>,  // live order cl_ord_id's, in order of issue
+    pub position: Option<Position>,
+    pub risk: RiskEngine,  // pre-trade checks of new orders and amends
+    pub dead_mans_switch: Option<DeadMansSwitch>,
+    pub connection: ConnectionState,
     pub status: String,
+    pub input: Option<String>,  // input line whilst typed
     pub has_refreshed: bool,  // FIXME: shouldn't be public...
-    pub order_type_ind: usize
+    pub order_type_ind: usize,
+    pub time_in_force_ind: usize,
+    pub trigger_price_ind: usize,
+    pub post_only: bool,
+    pub reduce_only: bool,
+    pub close_on_trigger: bool,
 }
 
 impl State {
-    pub fn new(qty: f64, qty_increment: f64)-> Self {
-        State { bid: -1.0, ask: -1.0, qty, qty_increment, order: None, status: "".to_string(), has_refreshed: false, order_type_ind: 0 }
+    pub fn new(qty: Decimal, qty_increment: Decimal, tick_size: Decimal, trigger_offset: Decimal)-> Self {
+        State { bid: Decimal::NEGATIVE_ONE, ask: Decimal::NEGATIVE_ONE, bids: Vec::new(), asks: Vec::new(), recent_trades: VecDeque::new(), tape_size: DEFAULT_TAPE_SIZE, large_trade_size: Decimal::MAX, qty, qty_increment, lot_size: Decimal::ONE, tick_size, instrument: None, price_input: None, trigger_offset, orders: HashMap::new(), order_ids: Vec::new(), position: None, risk: RiskEngine::default(), dead_mans_switch: None, connection: ConnectionState::Connecting, status: "".to_string(), input: None, has_refreshed: false, order_type_ind: 0,
+                time_in_force_ind: 0, trigger_price_ind: 0, post_only: false, reduce_only: false, close_on_trigger: false }
+    }
+
+    pub fn add_order(&mut self, order: ExchangeOrder) {
+        if !self.orders.contains_key(&order.cl_ord_id) {
+            self.order_ids.push(order.cl_ord_id.clone());
+        }
+        self.orders.insert(order.cl_ord_id.clone(), order);
+    }
+
+    pub fn remove_order(&mut self, cl_ord_id: &str) -> Option<ExchangeOrder> {
+        self.order_ids.retain(|x| x != cl_ord_id);
+        self.orders.remove(cl_ord_id)
+    }
+
+    /// Live orders, in order of issue.
+    pub fn live_orders(&self) -> impl Iterator<Item=&ExchangeOrder> {
+        self.order_ids.iter().filter_map(move |x| self.orders.get(x))
+    }
+
+    pub fn last_order(&self) -> Option<&ExchangeOrder> {
+        self.order_ids.last().and_then(|x| self.orders.get(x))
+    }
+
+    pub fn max_order_qty(&self) -> Option<Decimal> {
+        self.instrument.as_ref().and_then(|x| x.max_order_qty)
+    }
+
+    pub fn is_inverse(&self) -> bool {
+        self.instrument.as_ref().is_some_and(|x| x.is_inverse)
+    }
+
+    /// Mark price as per the position, else the mid.
+    pub fn mark_price(&self) -> Option<Decimal> {
+        self.position.as_ref().and_then(|x| x.mark_price)
+            .or_else(|| Some((self.bid + self.ask) / Decimal::TWO).filter(|_| self.bid > Decimal::ZERO && self.ask > Decimal::ZERO))
+    }
+
+    /// Realised plus unrealised PnL of own fills, since the session start or the last kill switch unlock.
+    pub fn session_pnl(&self) -> Decimal {
+        self.risk.session_pnl(self.mark_price(), self.instrument.as_ref())
+    }
+
+    /// Price of new orders, given the touch.
+    pub fn entry_price(&self, touch: Decimal) -> Decimal {
+        self.price_input.map_or(touch, |x| x.apply(touch, self.tick_size))
+    }
+
+    pub fn add_trade(&mut self, trade: Trade) {
+        self.recent_trades.push_front(trade);
+        self.recent_trades.truncate(self.tape_size);
     }
 
     pub fn order_type(&self) -> OrderType {
@@ -88,6 +471,40 @@ impl State {
     pub fn rotate_order_type(&mut self) {
         self.order_type_ind = (self.order_type_ind + 1) % ALL_ORDER_TYPES.len()
     }
+
+    pub fn time_in_force(&self) -> TimeInForce {
+        ALL_TIME_IN_FORCES[self.time_in_force_ind]
+    }
+
+    pub fn rotate_time_in_force(&mut self) {
+        self.time_in_force_ind = (self.time_in_force_ind + 1) % ALL_TIME_IN_FORCES.len()
+    }
+
+    pub fn trigger_price(&self) -> ExecInst {
+        ALL_TRIGGER_PRICES[self.trigger_price_ind]
+    }
+
+    pub fn rotate_trigger_price(&mut self) {
+        self.trigger_price_ind = (self.trigger_price_ind + 1) % ALL_TRIGGER_PRICES.len()
+    }
+
+    /// Execution instructions applicable to the order type.
+    pub fn exec_insts(&self, ord_type: OrderType) -> Vec<ExecInst> {
+        let mut exec_insts = Vec::new();
+        if self.post_only && ord_type.has_limit_price() {
+            exec_insts.push(ExecInst::ParticipateDoNotInitiate);
+        }
+        if self.reduce_only {
+            exec_insts.push(ExecInst::ReduceOnly);
+        }
+        if self.close_on_trigger && ord_type.is_conditional() {
+            exec_insts.push(ExecInst::Close);
+        }
+        if ord_type.is_conditional() {
+            exec_insts.push(self.trigger_price());
+        }
+        exec_insts
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -97,15 +514,52 @@ pub struct AppConfig {
     pub api_key:    String,
     pub api_secret: String,
     pub symbol:     String,
-    pub init_qty:   f64,
-    pub qty_inc:    f64,
+    pub init_qty:   Decimal,
+    pub qty_inc:    Decimal,
+    pub lot_size:   Decimal,
+    pub tick_size:  Decimal,
+    pub trigger_offset: Decimal,
+    pub tape_size:  usize,
+    pub large_trade_size: Decimal,
+    pub heartbeat_interval_secs: u64,
+    pub heartbeat_timeout_secs:  u64,
+    pub paper_trading: bool,
     pub wss_subscriptions: Vec<String>,
+    pub recording:  Option<RecordingConfig>,
+    pub risk:       Option<RiskLimits>,
+    pub dead_mans_switch: Option<DeadMansSwitchConfig>,
+    pub keys:       Option<HashMap<String, Vec<String>>>,  // action -> keys, see KeyBindings
+}
+
+/// Orders are canceled by the exchange unless the switch is refreshed within timeout_secs, every refresh_secs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadMansSwitchConfig {
+    pub timeout_secs: u64,
+    pub refresh_secs: u64,
 }
 
 impl AppConfig {
     pub fn new(config_filename: &str) -> Result<Self, ConfigError> {
         let mut c = Config::new();
         c.merge(File::with_name(config_filename))?;
-        c.try_into()
+        let cfg: AppConfig = c.try_into()?;
+        // a zero interval would ping on every poll, a zero timeout reconnect at once
+        if cfg.heartbeat_interval_secs == 0 || cfg.heartbeat_timeout_secs == 0 {
+            return Err(ConfigError::Message(format!("heartbeat_interval_secs {} and heartbeat_timeout_secs {} must be positive", cfg.heartbeat_interval_secs, cfg.heartbeat_timeout_secs)))
+        }
+        if let Some(dead_mans_switch) = cfg.dead_mans_switch.as_ref() {
+            dead_mans_switch.validate()?;
+        }
+        Ok(cfg)
+    }
+}
+
+impl DeadMansSwitchConfig {
+    /// Refreshes must be frequent enough to re-arm the switch before it fires.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.refresh_secs == 0 || self.refresh_secs >= self.timeout_secs {
+            return Err(ConfigError::Message(format!("dead_mans_switch: refresh_secs {} must be positive and below timeout_secs {}", self.refresh_secs, self.timeout_secs)))
+        }
+        Ok(())
     }
 }
\ No newline at end of file
@@ -1,10 +1,76 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use crate::exchange::{failure_events, Exchange};
 use crate::model;
-use model::{OrchestratorEvent, ExchangeCmd, ExchangeOrder, OrderStatus, Side, State, OrchestratorEvent::*, PriceType::*, ExchangeCmd::*};
+use model::{ConnectionState, OrchestratorEvent, ExchangeCmd, ExchangeOrder, ExecInst, OrderStatus, OrderType, PriceInput, Side, State, TimeInForce, round_to_step, OrchestratorEvent::*, PriceType::*, ExchangeCmd::*};
+use rust_decimal::Decimal;
 use uuid::Uuid;
 
 
+/// Drive process_event with events from rx, executing resulting ExchangeCmd's against the exchange and feeding its
+/// responses back via tx. on_refresh is invoked whenever the state has changed. Returns on Exit or a closed channel.
+/// The instrument is fetched upfront, ahead of any queued events, so orders are rounded to its tick and lot sizes.
+pub async fn run<E: Exchange>(exchange: &mut E, state: &mut State, rx: &mpsc::Receiver<OrchestratorEvent>, tx: &mpsc::Sender<OrchestratorEvent>, mut on_refresh: impl FnMut(&State)) {
+    match exchange.fetch_instrument().await {
+        Ok(events) => for e in events.iter() {
+            process_event(e, state);
+            if state.has_refreshed {
+                on_refresh(state);
+            }
+        }
+        Err(err) => log::error!("Failed to fetch instrument: {}", err),
+    }
+    loop {
+        match rx.recv() {
+            Ok(Exit) => {
+                // disarm the dead man's switch, if any
+                if let Some(cmd) = process_event(&Exit, state) {
+                    if let Err(err) = exchange.execute(&cmd).await {
+                        log::error!("Failed to execute {:?}: {}", cmd, err);
+                    }
+                }
+                break
+            }
+            Ok(e) => {
+                for exchange_event in exchange.on_event(&e) {
+                    tx.send(exchange_event).expect("Failed to send event");
+                }
+                if let Some(cmd) = process_event(&e, state) {
+                    match exchange.execute(&cmd).await {
+                        Ok(exchange_events) => exchange_events.into_iter().for_each(|x| tx.send(x).expect("Failed to send event")),
+                        Err(err) => {
+                            log::error!("Failed to execute {:?}: {}", cmd, err);
+                            failure_events(&cmd, &err).into_iter().for_each(|x| tx.send(x).expect("Failed to send event"));
+                        }
+                    }
+                }
+                if state.has_refreshed {
+                    on_refresh(state);
+                }
+            }
+            Err(err) => {
+                log::error!("mpsc channel receive error: {:?}", err);
+                break
+            }
+        }
+    }
+}
+
+/// Prompt the orchestrator to refresh the dead man's switch every refresh interval, till the channel is closed.
+pub fn spawn_dead_mans_switch_timer(refresh: Duration, tx: mpsc::Sender<OrchestratorEvent>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while tx.send(RefreshDeadMansSwitch).is_ok() {
+            thread::sleep(refresh);
+        }
+    })
+}
+
 /// DSL for converting OrchestratorEvent's and state into state and optional ExchangeCmd, eg.
-#[allow(clippy::float_cmp)]
+/// New orders and amends are subject to the pre-trade risk limits, rejections surfacing as the status instead.
 pub fn process_event<'a>(event: &'a OrchestratorEvent, state: &'a mut State) -> Option<ExchangeCmd<'a>> {  // probably need dyn...
     state.has_refreshed = false;
     match event {
@@ -19,85 +85,240 @@ pub fn process_event<'a>(event: &'a OrchestratorEvent, state: &'a mut State) ->
             state.qty -= state.qty_increment;
             None
         }
+        UpTriggerOffset => {
+            state.has_refreshed = true;
+            state.trigger_offset += state.tick_size;
+            None
+        }
+        DownTriggerOffset if state.trigger_offset < state.tick_size => None,  // ignore, cannot decrease < 0
+        DownTriggerOffset => {
+            state.has_refreshed = true;
+            state.trigger_offset -= state.tick_size;
+            None
+        }
+        RotateTimeInForce => {
+            state.rotate_time_in_force();
+            state.has_refreshed = true;
+            None
+        }
+        RotateTriggerPrice => {
+            state.rotate_trigger_price();
+            state.has_refreshed = true;
+            None
+        }
+        TogglePostOnly => {
+            state.post_only = !state.post_only;
+            state.has_refreshed = true;
+            None
+        }
+        ToggleReduceOnly => {
+            state.reduce_only = !state.reduce_only;
+            state.has_refreshed = true;
+            None
+        }
+        ToggleCloseOnTrigger => {
+            state.close_on_trigger = !state.close_on_trigger;
+            state.has_refreshed = true;
+            None
+        }
+        EditInput(input) => {
+            state.input = input.clone();
+            state.has_refreshed = true;
+            None
+        }
+        SetQty(qty) if *qty <= Decimal::ZERO || !is_multiple_of(*qty, state.lot_size) => {
+            state.has_refreshed = true;
+            state.status = format!("Invalid qty {}, must be a positive multiple of lot size {}!", qty, state.lot_size);
+            None
+        }
+        SetQty(qty) if state.max_order_qty().is_some_and(|x| *qty > x) => {
+            state.has_refreshed = true;
+            state.status = format!("Invalid qty {}, exceeds max order qty {}!", qty, state.max_order_qty().unwrap());
+            None
+        }
+        SetQty(qty) => {
+            state.has_refreshed = true;
+            state.qty = *qty;
+            state.status = format!("Qty set to {}", qty);
+            None
+        }
+        SetPrice(Some(PriceInput::Absolute(price))) if *price <= Decimal::ZERO || !is_multiple_of(*price, state.tick_size) => {
+            state.has_refreshed = true;
+            state.status = format!("Invalid price {}, must be a positive multiple of tick size {}!", price, state.tick_size);
+            None
+        }
+        SetPrice(price_input) => {
+            state.has_refreshed = true;
+            state.price_input = *price_input;
+            state.status = price_input.map_or_else(|| "Price reset to touch".to_string(), |x| format!("Price set to {}", x));
+            None
+        }
+        Unlock if state.risk.is_locked => {
+            state.has_refreshed = true;
+            let session_pnl = state.session_pnl();
+            state.risk.unlock(session_pnl);
+            state.status = format!("Kill switch unlocked, at session PnL {}, loss limit applies afresh", fmt_pnl(session_pnl));
+            None
+        }
+        Unlock => {
+            state.has_refreshed = true;
+            state.status = "Kill switch not engaged, ignoring unlock!".to_string();
+            None
+        }
         RotateOrderType => {
             state.rotate_order_type();
             state.has_refreshed = true;
             None
         }
+        UpLastPrice | DownLastPrice if state.last_order().map_or_else(|| false, |x| x.is_amendable()) => {
+            let tick = if *event == UpLastPrice { state.tick_size } else { -state.tick_size };
+            let cl_ord_id = state.order_ids.last().unwrap();
+            let price = round_to_step(state.orders[cl_ord_id].price.unwrap() + tick, state.tick_size);
+            state.has_refreshed = true;
+            let now = Instant::now();
+            if let Err(err) = state.risk.check_amend(cl_ord_id, price, state, now) {
+                state.status = err;
+                return None
+            }
+            state.risk.on_sent(now);
+            // price kept till acked, by the amend response or the order feed
+            let order = &state.orders[cl_ord_id];
+            state.status = format!("Amending order {} price to {}", order.cl_ord_id, price);
+            Some(AmendOrder(ExchangeOrder { cl_ord_id: order.cl_ord_id.clone(), ord_status: order.ord_status, ord_type: None, price: Some(price), qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }))
+        }
+        UpLastPrice | DownLastPrice => {
+            state.has_refreshed = true;
+            state.status = "No working limit order, ignoring amend!".to_string();
+            None
+        }
         NewBid(bid) if state.bid == *bid => None,
         NewBid(bid) => {
             state.has_refreshed = true;
             state.bid = *bid;
-            None
+            check_loss_limit(state)
         }
         NewAsk(ask) if state.ask == *ask => None,
         NewAsk(ask) => {
             state.has_refreshed = true;
             state.ask = *ask;
+            check_loss_limit(state)
+        }
+        NewConnectionState(connection) if state.connection == *connection => None,
+        NewConnectionState(connection) => {
+            state.has_refreshed = true;
+            state.connection = *connection;
+            state.status = format!("Feed {}", connection);
+            if *connection == ConnectionState::Connecting || *connection == ConnectionState::Disconnected {
+                // prices are stale till refreshed by the new connection
+                state.bid = Decimal::NEGATIVE_ONE;
+                state.ask = Decimal::NEGATIVE_ONE;
+            }
             None
         }
-        Buy(_) | Sell(_) if state.bid < 0.0 || state.ask < 0.0 => {
+        NewInstrument(instrument) => {
             state.has_refreshed = true;
-            state.status = "Won't trade till ask/bid populated!".to_string();
+            state.tick_size = instrument.tick_size;
+            state.lot_size = instrument.lot_size;
+            // keep qty controls on lot multiples, and triggers on ticks
+            state.qty = round_to_step(state.qty, state.lot_size).max(state.lot_size);
+            state.qty_increment = round_to_step(state.qty_increment, state.lot_size).max(state.lot_size);
+            state.trigger_offset = round_to_step(state.trigger_offset, state.tick_size);
+            state.status = format!("Instrument {}: tick size {}, lot size {}, max order qty {}", instrument.symbol, instrument.tick_size, instrument.lot_size, instrument.max_order_qty.map_or_else(|| "-".to_string(), |x| x.to_string()));
+            state.instrument = Some(instrument.clone());
             None
         }
-        Buy(_) | Sell(_) if state.order.is_some() => {
+        NewDepth(bids, asks) => {
             state.has_refreshed = true;
-            state.status = format!("Won't trade whilst another trade {} is in force!", state.order.as_ref().unwrap().cl_ord_id);
+            state.bids = bids.clone();
+            state.asks = asks.clone();
             None
         }
-        Buy(price_type) => {
-            log::info!("Buy: {:?}, state: {:?}", event, state);
-            let cl_ord_id = Uuid::new_v4().to_string();
-            let price = match *price_type {
-                Bid => state.bid,
-                Ask => state.ask,
-            };
+        NewTrades(trades) => {
+            state.has_refreshed = true;
+            for trade in trades.iter() {
+                state.add_trade(trade.clone());
+            }
+            None
+        }
+        Buy(_) | Sell(_) if state.risk.is_locked => {
             state.has_refreshed = true;
-            state.status = format!("New buy order {} of {} @ {}", cl_ord_id, state.qty, price);
-            let new_order = ExchangeOrder { cl_ord_id, ord_status: OrderStatus::NotYetIssued, qty: Some(state.qty), price: Some(price), side: Some(Side::Buy), ord_type: Some(state.order_type()) };
-            state.order = Some(new_order.clone());
-            Some(IssueOrder(new_order))
+            state.status = "Won't trade whilst the kill switch is engaged, unlock to resume!".to_string();
+            None
+        }
+        Buy(_) | Sell(_) if state.connection != ConnectionState::Connected => {
+            state.has_refreshed = true;
+            state.status = format!("Won't trade whilst feed is {}!", state.connection);
+            None
+        }
+        Buy(_) | Sell(_) if state.bid < Decimal::ZERO || state.ask < Decimal::ZERO => {
+            state.has_refreshed = true;
+            state.status = "Won't trade till ask/bid populated!".to_string();
+            None
         }
-        Sell(price_type) => {
-            log::info!("Sell: {:?}, state: {:?}", event, state);
-            let cl_ord_id = Uuid::new_v4().to_string();
-            let price = match *price_type {
+        Buy(price_type) | Sell(price_type) => {
+            let side = if matches!(event, Buy(_)) { Side::Buy } else { Side::Sell };
+            log::info!("{}: {:?}, state: {:?}", side, event, state);
+            let touch = match *price_type {
                 Bid => state.bid,
                 Ask => state.ask,
             };
             state.has_refreshed = true;
-            state.status = format!("New sell order {} of {} @ {}", cl_ord_id, state.qty, price);
-            let new_order = ExchangeOrder { cl_ord_id, ord_status: OrderStatus::NotYetIssued, qty: Some(state.qty), price: Some(price), side: Some(Side::Sell), ord_type: Some(state.order_type()) };
-            state.order = Some(new_order.clone());
-            Some(IssueOrder(new_order))
+            let now = Instant::now();
+            match new_order(side, touch, state).and_then(|x| state.risk.check_order(&x, state, now).map(|_| x)) {
+                Ok(new_order) => {
+                    state.risk.on_sent(now);
+                    state.status = format!("New {} order {} of {} @ {:?} trigger {:?}", side.to_string().to_lowercase(), new_order.cl_ord_id, new_order.qty.unwrap(), new_order.price, new_order.stop_px);
+                    state.add_order(new_order.clone());
+                    Some(IssueOrder(new_order))
+                }
+                Err(err) => {
+                    state.status = err;
+                    None
+                }
+            }
         }
-        UpdateOrder(order) if state.order.as_ref().map_or_else(|| false, |x| x.cl_ord_id == order.cl_ord_id) => {
+        UpdateOrder(order) if state.orders.contains_key(&order.cl_ord_id) => {
             log::info!("UpdateOrder: {:?}", event);
-            let curr_order = state.order.as_ref().unwrap();
-            let side = order.side.unwrap_or_else(|| curr_order.side.unwrap());
-            let ord_type = order.ord_type.unwrap_or_else(|| curr_order.ord_type.unwrap());
-            let qty = order.qty.unwrap_or_else(|| curr_order.qty.unwrap());
-            let price = order.price.unwrap_or_else(|| curr_order.price.unwrap());
+            let curr_order = &state.orders[&order.cl_ord_id];
+            let merged_order = ExchangeOrder {
+                cl_ord_id:  order.cl_ord_id.clone(),
+                ord_status: order.ord_status,
+                ord_type:   order.ord_type.or(curr_order.ord_type),
+                price:      order.price.or(curr_order.price),
+                qty:        order.qty.or(curr_order.qty),
+                side:       order.side.or(curr_order.side),
+                cum_qty:    order.cum_qty.or(curr_order.cum_qty),
+                leaves_qty: order.leaves_qty.or(curr_order.leaves_qty),
+                avg_px:     order.avg_px.or(curr_order.avg_px),
+                stop_px:    order.stop_px.or(curr_order.stop_px),
+                time_in_force: order.time_in_force.or(curr_order.time_in_force),
+                exec_inst:  order.exec_inst.clone().or_else(|| curr_order.exec_inst.clone()),
+            };
+            let side = merged_order.side.unwrap();
+            let ord_type = merged_order.ord_type.unwrap();
             match order.ord_status {
                 OrderStatus::Canceled => {
                     state.status = format!("Canceled {} {} order: {}", side, ord_type, order.cl_ord_id);
-                    state.order = None;
+                    state.remove_order(&order.cl_ord_id);
                 }
                 OrderStatus::Filled => {
-                    state.status = format!("Filled {} {} order: {} of {} @ {}", side, ord_type, order.cl_ord_id, qty, price);
-                    state.order = None;
+                    state.status = format!("Filled {} {} order: {} of {} @ {}", side, ord_type, order.cl_ord_id, merged_order.qty.unwrap(), merged_order.avg_px.or(merged_order.price).or(merged_order.stop_px).unwrap());
+                    state.remove_order(&order.cl_ord_id);
+                }
+                OrderStatus::Rejected => {
+                    state.status = format!("Rejected {} {} order: {}", side, ord_type, order.cl_ord_id);
+                    state.remove_order(&order.cl_ord_id);
                 }
                 _ => {
-                    state.status = format!("Updated {} {} order: {} of {:?} @ {:?}", side, ord_type, order.cl_ord_id, qty, price);
-                    state.order = Some(ExchangeOrder { ord_status: order.ord_status, .. order.clone() });
+                    state.status = format!("Updated {} {} order: {} of {:?} @ {:?}", side, ord_type, order.cl_ord_id, merged_order.qty, merged_order.price);
+                    state.add_order(merged_order);
                 }
             };
             state.has_refreshed = true;
             None
         }
         UpdateOrder(order) => {
-            if order.cl_ord_id == "" {
+            if order.cl_ord_id.is_empty() {
                 log::info!("Ignoring external order update: {:?}, given current state: {:?}", order, state);
             } else {
                 log::info!("Ignoring update of order that has been potentially Filled/Cancelled, order {:?}, given current state: {:?}", order, state);
@@ -105,21 +326,212 @@ pub fn process_event<'a>(event: &'a OrchestratorEvent, state: &'a mut State) ->
             None
 
         }
-        CancelLast if state.order.as_ref().map_or_else(|| false, |x| x.ord_status == OrderStatus::New || x.ord_status == OrderStatus::NotYetIssued || x.ord_status == OrderStatus::PartiallyFilled) => {
-            let order = state.order.as_ref().unwrap();
+        CancelLast if state.last_order().map_or_else(|| false, |x| x.is_cancelable()) => {
+            let cl_ord_id = state.order_ids.last().unwrap();
             state.has_refreshed = true;
-            state.status = format!("Issued order cancel: {}", order.cl_ord_id);
-            Some(CancelOrder(&order.cl_ord_id ))
+            state.status = format!("Issued order cancel: {}", cl_ord_id);
+            Some(CancelOrder(cl_ord_id))
         }
         CancelLast => {
             state.has_refreshed = true;
             state.status = "No order active, ignoring cancel!".to_string();
             None
         }
+        CancelAt(pos) if state.live_orders().nth(pos.wrapping_sub(1)).map_or_else(|| false, |x| x.is_cancelable()) => {
+            let cl_ord_id = &state.order_ids[pos - 1];
+            state.has_refreshed = true;
+            state.status = format!("Issued order cancel: {}", cl_ord_id);
+            Some(CancelOrder(cl_ord_id))
+        }
+        CancelAt(pos) => {
+            state.has_refreshed = true;
+            state.status = format!("No order active at {}, ignoring cancel!", pos);
+            None
+        }
+        CancelAll => {
+            state.has_refreshed = true;
+            let orders = &state.orders;
+            let cl_ord_ids = state.order_ids.iter().filter(|x| orders[*x].is_cancelable()).map(String::as_str).collect::<Vec<&str>>();
+            if cl_ord_ids.is_empty() {
+                state.status = "No orders active, ignoring cancel all!".to_string();
+                None
+            } else {
+                state.status = format!("Issued cancel of {} orders", cl_ord_ids.len());
+                Some(CancelOrders(cl_ord_ids))
+            }
+        }
+        FlattenAll => {
+            state.has_refreshed = true;
+            state.status = "Flattening all: canceling all orders and closing the position...".to_string();
+            log::warn!("Flatten all, state: {:?}", state);
+            // unlike CancelAll, not limited to the orders known to State, nor sized off its possibly stale position
+            let close_order = ExchangeOrder { cl_ord_id: Uuid::new_v4().to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(OrderType::Market), price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None,
+                time_in_force: Some(TimeInForce::ImmediateOrCancel), exec_inst: Some(vec!(ExecInst::Close)) };
+            Some(CancelAllAndClose(close_order))
+        }
+        NewFill(fill) => {
+            log::info!("NewFill: {:?}", event);
+            // every own execution counts towards the session PnL, incl. those of orders already Filled/removed, or never
+            // tracked, eg. the flatten all close order
+            state.risk.on_fill(fill, state.instrument.as_ref());
+            state.has_refreshed = true;
+            match state.orders.get_mut(&fill.cl_ord_id) {
+                Some(order) if fill.cum_qty.is_some_and(|x| order.cum_qty.unwrap_or(Decimal::ZERO) >= x) =>
+                    log::info!("Fill already accounted for in order: {:?}", order),
+                Some(order) => {
+                    let prev_cum_qty = order.cum_qty.unwrap_or(Decimal::ZERO);
+                    let cum_qty = prev_cum_qty + fill.last_qty;
+                    let avg_px = (order.avg_px.unwrap_or(Decimal::ZERO) * prev_cum_qty + fill.last_px * fill.last_qty) / cum_qty;
+                    let leaves_qty = order.qty.map_or(Decimal::ZERO, |x| x - cum_qty).max(Decimal::ZERO);
+                    order.cum_qty = Some(cum_qty);
+                    order.avg_px = Some(avg_px);
+                    order.leaves_qty = Some(leaves_qty);
+                    let ord_type = order.ord_type.unwrap();
+                    let fill_desc = order.fill_desc();
+                    if leaves_qty > Decimal::ZERO {
+                        order.ord_status = OrderStatus::PartiallyFilled;
+                        state.status = format!("Partially filled {} {} order: {} {}", fill.side, ord_type, fill.cl_ord_id, fill_desc);
+                    } else {
+                        state.status = format!("Filled {} {} order: {} {}", fill.side, ord_type, fill.cl_ord_id, fill_desc);
+                        state.remove_order(&fill.cl_ord_id);
+                    }
+                }
+                None => log::info!("Fill of untracked order: {:?}", fill)
+            }
+            check_loss_limit(state)
+        }
+        SyncLiveOrders(cl_ord_ids) => {
+            // drop orders closed whilst disconnected, retaining those still in flight
+            let stale_cl_ord_ids = state.live_orders()
+                .filter(|x| x.ord_status != OrderStatus::NotYetIssued && !cl_ord_ids.contains(&x.cl_ord_id))
+                .map(|x| x.cl_ord_id.clone())
+                .collect::<Vec<String>>();
+            if !stale_cl_ord_ids.is_empty() {
+                log::info!("Dropping orders no longer open on the exchange: {:?}", stale_cl_ord_ids);
+                for cl_ord_id in stale_cl_ord_ids.iter() {
+                    state.remove_order(cl_ord_id);
+                }
+                state.has_refreshed = true;
+                state.status = format!("Resynced orders, dropped {}", stale_cl_ord_ids.len());
+            }
+            None
+        }
+        UpdatePosition(position) => {
+            let merged_position = state.position.as_ref().map_or_else(|| position.clone(), |x| x.merge(position));
+            if state.position.as_ref() != Some(&merged_position) {
+                state.has_refreshed = true;
+                state.position = Some(merged_position);
+            }
+            check_loss_limit(state)
+        }
         NewStatus(status) => {
+            state.has_refreshed = true;
             state.status = status.to_string();
             None
         }
-        _ => None
+        RefreshDeadMansSwitch => {
+            // fired by the exchange, eg. whilst left to expire or should the refreshes have failed
+            if let Some(dead_mans_switch) = state.dead_mans_switch.as_mut().filter(|x| x.cancel_at.is_some_and(|x| x <= Utc::now())) {
+                dead_mans_switch.cancel_at = None;
+                state.has_refreshed = true;
+                state.status = "Dead man's switch expired, orders canceled by the exchange!".to_string();
+            }
+            // left to expire whilst the feed is unhealthy, as orders can't be managed blind
+            if state.connection != ConnectionState::Connected {
+                if state.dead_mans_switch.is_some() {
+                    log::warn!("Not refreshing dead man's switch whilst feed is {}", state.connection);
+                }
+                return None
+            }
+            state.dead_mans_switch.as_ref().map(|x| CancelAllAfter(x.timeout))
+        }
+        UpdateDeadMansSwitch(cancel_at) => {
+            if let Some(dead_mans_switch) = state.dead_mans_switch.as_mut() {
+                state.has_refreshed = true;
+                if dead_mans_switch.cancel_at.is_some() != cancel_at.is_some() {
+                    state.status = if cancel_at.is_some() { format!("Dead man's switch armed, {}s timeout", dead_mans_switch.timeout.as_secs()) } else { "Dead man's switch disarmed".to_string() };
+                }
+                dead_mans_switch.cancel_at = *cancel_at;
+            }
+            None
+        }
+        Exit => state.dead_mans_switch.as_ref().map(|_| CancelAllAfter(Duration::ZERO)),
     }
 }
+
+/// Engage the kill switch once the session loss limit is crossed: cancel all orders of the symbol, optionally flattening
+/// the position with a reduce-only market order, and lock out new orders till unlocked.
+fn check_loss_limit(state: &mut State) -> Option<ExchangeCmd<'_>> {
+    let session_pnl = state.session_pnl();
+    if !state.risk.is_loss_limit_crossed(session_pnl) {
+        return None
+    }
+    state.risk.is_locked = true;
+    state.has_refreshed = true;
+    let flatten_order = if state.risk.limits.flatten_on_kill { flatten_order(state) } else { None };
+    let flatten_desc = flatten_order.as_ref().map_or_else(|| "".to_string(), |x| format!(", flattening via {} {}", x.side.unwrap().to_string().to_lowercase(), x.qty.unwrap()));
+    state.status = format!("Kill switch engaged at session PnL {}, canceling all orders{}, locked till unlocked!", fmt_pnl(session_pnl), flatten_desc);
+    log::warn!("{}, state: {:?}", state.status, state);
+    // the flatten order is left untracked, as the close order of FlattenAll, its fills counting towards the PnL regardless
+    match flatten_order {
+        Some(order) => Some(Batch(vec!(CancelAllOrders, IssueOrder(order)))),
+        None => Some(CancelAllOrders)
+    }
+}
+
+/// Reduce-only market order closing the position, as per the exchange, else as per own fills.
+fn flatten_order(state: &State) -> Option<ExchangeOrder> {
+    let position_qty = state.position.as_ref().and_then(|x| x.current_qty).unwrap_or(state.risk.pnl.position_qty);
+    if position_qty.is_zero() {
+        return None
+    }
+    let side = if position_qty > Decimal::ZERO { Side::Sell } else { Side::Buy };
+    let touch = if side == Side::Buy { state.ask } else { state.bid };
+    Some(ExchangeOrder { cl_ord_id: Uuid::new_v4().to_string(), ord_status: OrderStatus::NotYetIssued, qty: Some(position_qty.abs()), price: Some(touch).filter(|x| *x > Decimal::ZERO), side: Some(side), ord_type: Some(OrderType::Market),
+        cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: Some(TimeInForce::ImmediateOrCancel), exec_inst: Some(vec!(ExecInst::ReduceOnly)) })
+}
+
+/// PnL sans insignificant digits, eg. of 1/price for inverse contracts.
+fn fmt_pnl(pnl: Decimal) -> Decimal {
+    pnl.round_dp(8).normalize()
+}
+
+/// New order off the touch as per the order entry settings, its qty rounded to the lot size and prices to the tick size.
+fn new_order(side: Side, touch: Decimal, state: &State) -> Result<ExchangeOrder, String> {
+    let qty = round_to_step(state.qty, state.lot_size);
+    if qty <= Decimal::ZERO {
+        return Err(format!("Won't trade qty {}, below lot size {}!", state.qty, state.lot_size));
+    }
+    if let Some(max_order_qty) = state.max_order_qty().filter(|x| qty > *x) {
+        return Err(format!("Won't trade qty {}, above max order qty {}!", qty, max_order_qty));
+    }
+    let (price, stop_px) = order_prices(side, state.order_type(), state.entry_price(touch), state.trigger_offset);
+    let (price, stop_px) = (price.map(|x| round_to_step(x, state.tick_size)), stop_px.map(|x| round_to_step(x, state.tick_size)));
+    if price.into_iter().chain(stop_px).any(|x| x <= Decimal::ZERO) {
+        return Err(format!("Won't trade @ {:?} trigger {:?}, prices must be positive!", price, stop_px));
+    }
+    Ok(ExchangeOrder { cl_ord_id: Uuid::new_v4().to_string(), ord_status: OrderStatus::NotYetIssued, qty: Some(qty), price, side: Some(side), ord_type: Some(state.order_type()), cum_qty: None, leaves_qty: None, avg_px: None, stop_px,
+        time_in_force: Some(state.time_in_force()), exec_inst: Some(state.exec_insts(state.order_type())) })
+}
+
+fn is_multiple_of(x: Decimal, step: Decimal) -> bool {
+    (x % step).is_zero()
+}
+
+/// Derive (price, stopPx) from the bid/ask, with triggers placed trigger_offset away from it:
+/// - Stop/StopLimit trigger on a breakout, ie. above the touch for Buy, below for Sell
+/// - MarketIfTouched/LimitIfTouched trigger on a pullback, ie. below the touch for Buy, above for Sell
+fn order_prices(side: Side, ord_type: OrderType, touch: Decimal, trigger_offset: Decimal) -> (Option<Decimal>, Option<Decimal>) {
+    let breakout_offset = if side == Side::Buy { trigger_offset } else { -trigger_offset };
+    let stop_px = match ord_type {
+        OrderType::Stop | OrderType::StopLimit => Some(touch + breakout_offset),
+        OrderType::MarketIfTouched | OrderType::LimitIfTouched => Some(touch - breakout_offset),
+        OrderType::Limit | OrderType::Market => None,
+    };
+    let price = match ord_type {
+        OrderType::Limit | OrderType::Market => Some(touch),  // Market's price is indicative only
+        OrderType::StopLimit | OrderType::LimitIfTouched => stop_px,
+        OrderType::Stop | OrderType::MarketIfTouched => None,
+    };
+    (price, stop_px)
+}
@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::model::{Level, Side};
+use crate::ws_model::{OrderBookL2Row, TableAction};
+
+
+/// Local L2 order book, maintained from orderBookL2/orderBookL2_25 partial/insert/update/delete actions, keyed by level id.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    is_synced: bool,
+    levels: HashMap<u64, (Side, Decimal)>,  // level id -> side, price
+    bids: BTreeMap<Decimal, Decimal>,       // price -> size
+    asks: BTreeMap<Decimal, Decimal>,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook::default()
+    }
+
+    /// Whether the partial has been received, and the book has been consistent since.
+    pub fn is_synced(&self) -> bool {
+        self.is_synced
+    }
+
+    /// Apply a table action. Actions preceding the partial are ignored, as advised by BitMEX.
+    /// On error, the book is cleared and a new partial should be requested.
+    pub fn apply(&mut self, action: &TableAction, rows: &[OrderBookL2Row]) -> Result<(), OrderBookError> {
+        let res = match action {
+            TableAction::Partial => {
+                self.clear();
+                self.is_synced = true;
+                rows.iter().try_for_each(|x| self.insert(x))
+            }
+            _ if !self.is_synced => Ok(()),
+            TableAction::Insert => rows.iter().try_for_each(|x| self.insert(x)),
+            TableAction::Update => rows.iter().try_for_each(|x| self.update(x)),
+            TableAction::Delete => rows.iter().try_for_each(|x| self.delete(x)),
+        }.and_then(|_| self.check_consistency());
+        if res.is_err() {
+            self.clear();
+        }
+        res
+    }
+
+    pub fn clear(&mut self) {
+        self.is_synced = false;
+        self.levels.clear();
+        self.bids.clear();
+        self.asks.clear();
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.depth(Side::Buy, 1).pop()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.depth(Side::Sell, 1).pop()
+    }
+
+    /// Top n levels of a side, best first.
+    pub fn depth(&self, side: Side, n: usize) -> Vec<Level> {
+        let to_level = |(price, size): (&Decimal, &Decimal)| Level { price: *price, size: *size };
+        match side {
+            Side::Buy  => self.bids.iter().rev().take(n).map(to_level).collect(),
+            Side::Sell => self.asks.iter().take(n).map(to_level).collect(),
+        }
+    }
+
+    /// Cumulative size of the top n levels of a side.
+    pub fn cum_size(&self, side: Side, n: usize) -> Decimal {
+        self.depth(side, n).iter().map(|x| x.size).sum()
+    }
+
+    fn side_levels(&mut self, side: Side) -> &mut BTreeMap<Decimal, Decimal> {
+        match side {
+            Side::Buy  => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+
+    fn insert(&mut self, row: &OrderBookL2Row) -> Result<(), OrderBookError> {
+        if self.levels.contains_key(&row.id) {
+            return Err(OrderBookError::DuplicateLevel(row.id));
+        }
+        let price = row.price.ok_or(OrderBookError::MissingField(row.id, "price"))?;
+        let size = row.size.ok_or(OrderBookError::MissingField(row.id, "size"))?;
+        self.levels.insert(row.id, (row.side, price));
+        self.side_levels(row.side).insert(price, size);
+        Ok(())
+    }
+
+    fn update(&mut self, row: &OrderBookL2Row) -> Result<(), OrderBookError> {
+        let (side, price) = *self.levels.get(&row.id).ok_or(OrderBookError::UnknownLevel(row.id))?;
+        let size = row.size.ok_or(OrderBookError::MissingField(row.id, "size"))?;
+        self.side_levels(side).insert(price, size);
+        Ok(())
+    }
+
+    fn delete(&mut self, row: &OrderBookL2Row) -> Result<(), OrderBookError> {
+        let (side, price) = self.levels.remove(&row.id).ok_or(OrderBookError::UnknownLevel(row.id))?;
+        self.side_levels(side).remove(&price);
+        Ok(())
+    }
+
+    fn check_consistency(&self) -> Result<(), OrderBookError> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) if bid.price >= ask.price => Err(OrderBookError::CrossedBook(bid.price, ask.price)),
+            _ => Ok(())
+        }
+    }
+}
+
+
+#[derive(Error, Debug, PartialEq)]
+pub enum OrderBookError {
+    #[error("level {0} already exists")]
+    DuplicateLevel(u64),
+    #[error("level {0} not found")]
+    UnknownLevel(u64),
+    #[error("level {0} missing {1}")]
+    MissingField(u64, &'static str),
+    #[error("crossed book, bid {0} >= ask {1}")]
+    CrossedBook(Decimal, Decimal),
+}
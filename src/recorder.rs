@@ -0,0 +1,117 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+
+/// Recording of raw WS text frames, as rotating JSONL files named ws-<session start>-<seq>.jsonl[.gz] in dir.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingConfig {
+    pub dir: String,
+    pub max_file_bytes: u64,  // uncompressed, rotating once exceeded
+    pub compress: bool,
+}
+
+/// A received text frame, as recorded per JSONL line.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct RecordedFrame {
+    pub received_at: DateTime<Utc>,
+    pub frame: String,
+}
+
+pub struct Recorder {
+    config: RecordingConfig,
+    session_start: DateTime<Utc>,
+    seq: u32,
+    writer: RecordingWriter,
+    file_bytes: u64,
+}
+
+enum RecordingWriter {
+    Plain(BufWriter<File>),
+    Compressed(GzEncoder<BufWriter<File>>),
+}
+
+impl RecordingWriter {
+    fn as_write(&mut self) -> &mut dyn Write {
+        match self {
+            RecordingWriter::Plain(x) => x,
+            RecordingWriter::Compressed(x) => x,
+        }
+    }
+
+    /// Flush, writing the gzip trailer of compressed files.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            RecordingWriter::Plain(mut x) => x.flush(),
+            RecordingWriter::Compressed(x) => x.finish()?.flush(),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(config: RecordingConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let session_start = Utc::now();
+        let writer = open_writer(&config, session_start, 0)?;
+        Ok(Recorder { config, session_start, seq: 0, writer, file_bytes: 0 })
+    }
+
+    /// Append a frame, rotating the file once max_file_bytes is exceeded. Uncompressed recordings are flushed per frame,
+    /// compressed on rotation and finish.
+    pub fn record(&mut self, received_at: DateTime<Utc>, frame: &str) -> io::Result<()> {
+        let mut line = serde_json::to_string(&RecordedFrame { received_at, frame: frame.to_string() })?;
+        line.push('\n');
+        self.writer.as_write().write_all(line.as_bytes())?;
+        if !self.config.compress {
+            self.writer.as_write().flush()?;
+        }
+        self.file_bytes += line.len() as u64;
+        if self.file_bytes >= self.config.max_file_bytes {
+            self.seq += 1;
+            let writer = open_writer(&self.config, self.session_start, self.seq)?;
+            std::mem::replace(&mut self.writer, writer).finish()?;
+            self.file_bytes = 0;
+        }
+        Ok(())
+    }
+
+    /// Finish the current file, which is otherwise left truncated if compressed.
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+fn open_writer(config: &RecordingConfig, session_start: DateTime<Utc>, seq: u32) -> io::Result<RecordingWriter> {
+    let extension = if config.compress { "jsonl.gz" } else { "jsonl" };
+    let path = Path::new(&config.dir).join(format!("ws-{}-{:04}.{}", session_start.format("%Y%m%dT%H%M%S%.3f"), seq, extension));
+    log::info!("Recording ws feed to {:?}", path);
+    let file = BufWriter::new(File::create(path)?);
+    Ok(if config.compress { RecordingWriter::Compressed(GzEncoder::new(file, Compression::default())) } else { RecordingWriter::Plain(file) })
+}
+
+/// Read a recording, decompressing .gz files.
+pub fn read_recording(path: &Path) -> io::Result<impl Iterator<Item=io::Result<RecordedFrame>>> {
+    let file = File::open(path)?;
+    let reader: Box<dyn BufRead> = if path.extension().is_some_and(|x| x == "gz") {
+        Box::new(BufReader::new(GzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+    Ok(reader.lines().map(|line| line.and_then(|x| serde_json::from_str(&x).map_err(io::Error::from))))
+}
+
+/// Recording files in dir, in recording order.
+pub fn recording_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.retain(|x| x.file_name().and_then(|x| x.to_str()).is_some_and(|x| x.starts_with("ws-") && (x.ends_with(".jsonl") || x.ends_with(".jsonl.gz"))));
+    paths.sort();
+    Ok(paths)
+}
@@ -1,19 +1,106 @@
-use model::State;
+use std::cmp::min;
+
+use rust_decimal::Decimal;
+
+use model::{Side, State};
 
 use crate::model;
 
+const LADDER_LEVELS: usize = 5;
+const TAPE_ROWS: usize = 10;
+
 
 /// Render the UI according to the current state.
 pub fn render_state(header: &str, state: &State) -> String {
-    let recent_order_if_present = match state.order {
-        Some(ref o) => format!("\r\nCURR ORDER: {} {} {} {:.2} @ {:.2}", o.side.unwrap(), o.ord_type.unwrap(), o.ord_status, o.qty.unwrap(), o.price.unwrap()),
+    let price = |x: Decimal| fmt_price(x, state.tick_size);
+    let exact = |x: Decimal| x.to_string();
+    let live_orders = state.live_orders().enumerate()
+        .map(|(i, o)| {
+            let fill_desc = if o.cum_qty.unwrap_or(Decimal::ZERO) > Decimal::ZERO { format!(" {}", o.fill_desc()) } else { "".to_string() };
+            let trigger_desc = o.stop_px.map_or_else(|| "".to_string(), |x| format!(" trigger {}", price(x)));
+            format!("\r\nORDER {}: {} {} {} {} @ {}{}{}", i + 1, o.side.unwrap(), o.ord_type.unwrap(), o.ord_status, o.qty.unwrap(), fmt_opt(o.price, price), trigger_desc, fill_desc)
+        })
+        .collect::<String>();
+    let input_if_present = state.input.as_ref().map_or_else(|| "".to_string(), |x| format!("\r\n:{}", x));
+    let instrument_if_present = state.instrument.as_ref().map_or_else(|| "".to_string(), |x| format!("\r\nINSTRUMENT: {} TICK: {} LOT: {} MULTIPLIER: {} INVERSE: {} MAX QTY: {}",
+        x.symbol, x.tick_size, x.lot_size, x.multiplier, fmt_flag(x.is_inverse), x.max_order_qty.map_or_else(|| "-".to_string(), |x| x.to_string())));
+    let kill_switch_if_present = state.risk.limits.max_session_loss.map_or_else(|| "".to_string(), |x| format!("\r\nSESSION PNL: {} / LOSS LIMIT: {} / KILL SWITCH: {}",
+        state.session_pnl().round_dp(8).normalize(), x, if state.risk.is_locked { "ENGAGED" } else { "armed" }));
+    let dead_mans_switch_if_present = state.dead_mans_switch.as_ref().map_or_else(|| "".to_string(), |x| format!("\r\nDEAD MAN'S SWITCH: {}s / {}",
+        x.timeout.as_secs(), x.cancel_at.map_or_else(|| "disarmed".to_string(), |x| format!("armed till {}", x.format("%H:%M:%S")))));
+    let position_if_present = match state.position {
+        Some(ref p) => format!("\r\nPOSITION: {} @ {} LIQ: {} UPNL: {} RPNL: {} LEV: {}",
+                               fmt_opt(p.current_qty, exact), fmt_opt(p.avg_entry_price, price), fmt_opt(p.liquidation_price, price), fmt_opt(p.unrealised_pnl, exact), fmt_opt(p.realised_pnl, exact), fmt_opt(p.leverage, exact)),
         None => "".to_string()
     };
     format!("{}\r
 \r
-BID: {:.2} / ASK: {:.2}\r
-QTY: {:.2}\r
+FEED: {}\r
+BID: {} / ASK: {}\r
+QTY: {} / PRICE: {}\r
 ORDER TYPE: {}\r
-STATUS: {}{}",
-            header, state.bid, state.ask, state.qty, state.order_type(), state.status, recent_order_if_present)
+TRIGGER OFFSET: {} / TRIGGER PRICE: {}\r
+TIME IN FORCE: {}\r
+POST ONLY: {} / REDUCE ONLY: {} / CLOSE ON TRIGGER: {}\r
+STATUS: {}{}{}{}{}{}{}{}{}",
+            header, state.connection, price(state.bid), price(state.ask), state.qty, state.price_input.map_or_else(|| "touch".to_string(), |x| x.to_string()), state.order_type(), price(state.trigger_offset), state.trigger_price(), state.time_in_force(), fmt_flag(state.post_only), fmt_flag(state.reduce_only), fmt_flag(state.close_on_trigger), state.status, input_if_present, instrument_if_present, kill_switch_if_present, dead_mans_switch_if_present, position_if_present, live_orders, render_ladder(state), render_tape(state))
+}
+
+/// Render time and sales, most recent first, highlighting large trades.
+fn render_tape(state: &State) -> String {
+    if state.recent_trades.is_empty() {
+        return "".to_string()
+    }
+    let rows = state.recent_trades.iter().take(TAPE_ROWS).map(|x| {
+        let row = format!("{} {:<4} {:>10} @ {:>10} {:<2}", x.timestamp.format("%H:%M:%S%.3f"), x.side.to_string(), x.size, fmt_price(x.price, state.tick_size), x.tick_direction.symbol());
+        if x.size >= state.large_trade_size {
+            format!("\r\n{}{}{}", termion::style::Invert, row, termion::style::Reset)
+        } else {
+            format!("\r\n{}", row)
+        }
+    }).collect::<String>();
+    format!("\r\n\r\nTAPE:{}", rows)
+}
+
+/// Render price ladder around the spread, with own working orders and recent trades at each price.
+fn render_ladder(state: &State) -> String {
+    let bids = &state.bids[..min(LADDER_LEVELS, state.bids.len())];
+    let asks = &state.asks[..min(LADDER_LEVELS, state.asks.len())];
+    let (lowest, highest) = match (bids.last().or_else(|| asks.first()), asks.last().or_else(|| bids.first())) {
+        (Some(lowest), Some(highest)) => (lowest.price, highest.price),
+        _ => return "".to_string()
+    };
+    let mut prices = asks.iter().chain(bids.iter()).map(|x| x.price)
+        .chain(state.live_orders().filter_map(|x| x.price).filter(|x| lowest <= *x && *x <= highest))
+        .collect::<Vec<Decimal>>();
+    prices.sort_by(|a, b| b.cmp(a));
+    prices.dedup();
+
+    let fmt_size = |levels: &[model::Level], price: Decimal| levels.iter().find(|x| x.price == price).map_or_else(|| "".to_string(), |x| x.size.to_string());
+    let rows = prices.iter().map(|price| {
+        let order_qty = |side: Side| state.live_orders().filter(|x| x.price == Some(*price) && x.side == Some(side)).map(|x| x.qty.unwrap_or(Decimal::ZERO)).sum::<Decimal>();
+        let orders = match (order_qty(Side::Buy), order_qty(Side::Sell)) {
+            (buy_qty, sell_qty) if buy_qty > Decimal::ZERO && sell_qty > Decimal::ZERO => format!("B {} S {}", buy_qty, sell_qty),
+            (buy_qty, _) if buy_qty > Decimal::ZERO => format!("B {}", buy_qty),
+            (_, sell_qty) if sell_qty > Decimal::ZERO => format!("S {}", sell_qty),
+            _ => "".to_string()
+        };
+        let traded = state.recent_trades.iter().filter(|x| x.price == *price).map(|x| x.size).sum::<Decimal>();
+        let traded = if traded > Decimal::ZERO { traded.to_string() } else { "".to_string() };
+        format!("\r\n{:>12} | {:>10} | {:>10} | {:>10} | {:>10}", orders, fmt_size(bids, *price), fmt_price(*price, state.tick_size), fmt_size(asks, *price), traded)
+    }).collect::<String>();
+    format!("\r\n\r\n{:>12} | {:>10} | {:>10} | {:>10} | {:>10}{}", "ORDERS", "BID", "PRICE", "ASK", "TRADES", rows)
+}
+
+fn fmt_flag(x: bool) -> &'static str {
+    if x { "on" } else { "off" }
+}
+
+fn fmt_opt(x: Option<Decimal>, fmt: impl Fn(Decimal) -> String) -> String {
+    x.map_or_else(|| "-".to_string(), fmt)
+}
+
+/// Price to as many decimal places as the tick size.
+fn fmt_price(x: Decimal, tick_size: Decimal) -> String {
+    format!("{:.*}", tick_size.normalize().scale() as usize, x)
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::collections::VecDeque;
+use std::io;
+use std::iter::Peekable;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use chrono::{DateTime, Utc};
+
+use crate::model::{ConnectionState, ExchangeCmd, OrchestratorEvent, State};
+use crate::orchestrator::process_event;
+use crate::recorder::RecordedFrame;
+use crate::ws::FeedDecoder;
+use crate::ws_model::Response;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    RealTime,
+    Multiple(f64),
+    Max,
+}
+
+impl ReplaySpeed {
+    /// Parse "realtime", "max" or eg. "10x".
+    pub fn parse(s: &str) -> Option<ReplaySpeed> {
+        match s {
+            "realtime" => Some(ReplaySpeed::RealTime),
+            "max" => Some(ReplaySpeed::Max),
+            _ => s.strip_suffix('x').and_then(|x| x.parse::<f64>().ok()).filter(|x| *x > 0.0).map(ReplaySpeed::Multiple)
+        }
+    }
+
+    /// Twice as fast, realtime being 1x.
+    pub fn faster(&self) -> ReplaySpeed {
+        match self {
+            ReplaySpeed::RealTime => ReplaySpeed::Multiple(2.0),
+            ReplaySpeed::Multiple(x) => ReplaySpeed::Multiple(x * 2.0),
+            ReplaySpeed::Max => ReplaySpeed::Max,
+        }
+    }
+
+    /// Half as fast, realtime being 1x. Max has no multiple to halve, hence drops to realtime.
+    pub fn slower(&self) -> ReplaySpeed {
+        match self {
+            ReplaySpeed::RealTime => ReplaySpeed::Multiple(0.5),
+            ReplaySpeed::Max => ReplaySpeed::RealTime,
+            ReplaySpeed::Multiple(x) if *x / 2.0 == 1.0 => ReplaySpeed::RealTime,
+            ReplaySpeed::Multiple(x) => ReplaySpeed::Multiple(x / 2.0),
+        }
+    }
+
+    /// Wall clock delay for the recorded delay.
+    fn scale(&self, delay: time::Duration) -> time::Duration {
+        match self {
+            ReplaySpeed::RealTime => delay,
+            ReplaySpeed::Multiple(x) => delay.div_f64(*x),
+            ReplaySpeed::Max => time::Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayControl {
+    TogglePause,
+    Step,  // replay a single step, whilst paused
+    SetSpeed(ReplaySpeed),
+    Stop,
+}
+
+/// Events of a recorded frame or scripted event, at its (receive) time.
+#[derive(Debug, PartialEq)]
+pub struct ReplayStep {
+    pub at: DateTime<Utc>,
+    pub events: Vec<OrchestratorEvent>,
+}
+
+/// Recorded frames, decoded as the live feed would, merged in time order with scripted events, eg. key presses.
+pub struct Replay<I: Iterator<Item=io::Result<RecordedFrame>>> {
+    frames: Peekable<I>,
+    script: VecDeque<(DateTime<Utc>, OrchestratorEvent)>,
+    decoder: FeedDecoder,
+}
+
+impl<I: Iterator<Item=io::Result<RecordedFrame>>> Replay<I> {
+    pub fn new(frames: I, mut script: Vec<(DateTime<Utc>, OrchestratorEvent)>) -> Self {
+        script.sort_by_key(|(at, _)| *at);
+        Replay { frames: frames.peekable(), script: script.into(), decoder: FeedDecoder::new() }
+    }
+}
+
+impl<I: Iterator<Item=io::Result<RecordedFrame>>> Iterator for Replay<I> {
+    type Item = io::Result<ReplayStep>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_script_next = match (self.script.front(), self.frames.peek()) {
+            (Some((script_at, _)), Some(Ok(frame))) => *script_at <= frame.received_at,
+            (Some(_), None) => true,
+            _ => false
+        };
+        if is_script_next {
+            return self.script.pop_front().map(|(at, event)| Ok(ReplayStep { at, events: vec!(event) }));
+        }
+        self.frames.next().map(|frame| frame.map(|frame| {
+            // non table frames, eg. pongs, retain their timing but carry no events
+            let events = match serde_json::from_str::<Response>(&frame.frame) {
+                Ok(resp) => self.decoder.decode(&resp).0,
+                Err(_) => vec!()
+            };
+            ReplayStep { at: frame.received_at, events }
+        }))
+    }
+}
+
+/// Feed replay steps into process_event, paced as per speed and controls, the feed deemed connected throughout.
+/// Resulting ExchangeCmd's are passed to on_cmd, and on_refresh is invoked whenever the state has changed. Returns once
+/// the replay is exhausted or stopped.
+pub fn run<I: Iterator<Item=io::Result<RecordedFrame>>>(
+    replay: Replay<I>,
+    state: &mut State,
+    mut speed: ReplaySpeed,
+    controls: &mpsc::Receiver<ReplayControl>,
+    mut on_cmd: impl FnMut(&ExchangeCmd),
+    mut on_refresh: impl FnMut(&State)) -> io::Result<()> {
+    let mut is_paused = false;
+    let mut prev_at: Option<DateTime<Utc>> = None;
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), state);
+    on_refresh(state);
+    for step in replay {
+        let step = step?;
+        // whilst paused, block till resumed or stepped
+        loop {
+            let control = if is_paused { controls.recv().ok() } else { controls.try_recv().ok() };
+            match control {
+                Some(ReplayControl::TogglePause) => is_paused = !is_paused,
+                Some(ReplayControl::Step) if is_paused => break,
+                Some(ReplayControl::Step) => (),
+                Some(ReplayControl::SetSpeed(new_speed)) => speed = new_speed,
+                Some(ReplayControl::Stop) => return Ok(()),
+                None if is_paused => return Ok(()),  // controls disconnected
+                None => break
+            }
+        }
+        if let (false, Some(prev_at)) = (is_paused, prev_at) {
+            thread::sleep(speed.scale((step.at - prev_at).to_std().unwrap_or_default()));
+        }
+        prev_at = Some(step.at);
+
+        for event in step.events.iter() {
+            if let Some(cmd) = process_event(event, state) {
+                on_cmd(&cmd);
+            }
+            if state.has_refreshed {
+                on_refresh(state);
+            }
+        }
+    }
+    Ok(())
+}
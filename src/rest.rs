@@ -1,77 +1,106 @@
 use std::borrow::Cow;
+use std::time;
 
 use chrono::{Duration, Utc};
 use reqwest::StatusCode;
+use rust_decimal::Decimal;
 use thiserror::Error;
 
-use crate::model::{ExchangeOrder, OrchestratorEvent, OrchestratorEvent::*};
-use crate::model::OrderType::{Limit, Market};
-use crate::rest_model::{Order, Response};
+use crate::model::{self, ExchangeOrder, ExecInst, OrchestratorEvent, OrchestratorEvent::*, OrderStatus, TimeInForce};
+use crate::rest_model::{CancelAllAfter, Instrument, Order, Response};
 use crate::sign::sign;
 
 const API_ORDER_PATH: &str = "/api/v1/order";
+const API_ORDER_ALL_PATH: &str = "/api/v1/order/all";
+const API_CANCEL_ALL_AFTER_PATH: &str = "/api/v1/order/cancelAllAfter";
+const API_INSTRUMENT_PATH: &str = "/api/v1/instrument";
 
 
 /// Issue an Order.
-pub async fn issue_order<'a>(root_url: &'a str, api_key: &'a str, api_secret: &'a str, symbol: &'a str, order: &'a ExchangeOrder) -> Result<OrchestratorEvent, RestError> {
-    let url_params: Vec<(&str, Cow<'a, str>)> = match order {
-        ExchangeOrder { cl_ord_id, ord_type, price, qty, side, .. } if ord_type.map_or_else(|| false, |x| x == Limit) => {
-            vec![
-             ("symbol",  symbol.into()),
-             ("ordType",     "Limit".into()),
-             ("timeInForce", "GoodTillCancel".into()),
-             // ("execInst",    "ParticipateDoNotInitiate".into()),
-             ("orderQty",    qty.unwrap().to_string().into()),
-             ("side",        side.unwrap().to_string().into()),
-             ("price",       price.unwrap().to_string().into()),
-             ("clOrdID",     cl_ord_id.into())]
-            //format!("symbol={}&ordType={}&timeInForce=GoodTillCancel&orderQty={}&side={}&price={}&clOrdID={}", symbol, *ord_type, qty, side, price, cl_ord_id)
-        }
-        ExchangeOrder { cl_ord_id, ord_type, qty, side, .. } if ord_type.map_or_else(|| false, |x| x == Market) => {
-            //let qty_str = qty.to_string();
-            vec![("symbol",  symbol.into()),
-             ("ordType",     "Market".into()),
-             ("timeInForce", "GoodTillCancel".into()),
-             ("orderQty",    qty.unwrap().to_string().into()),
-             ("side",        side.unwrap().to_string().into()),
-             ("clOrdID",     cl_ord_id.into())]
-            //format!("symbol={}&ordType={}&timeInForce=GoodTillCancel&orderQty={}&side={}&clOrdID={}", symbol, *ord_type, qty, side, cl_ord_id)
-        }
-        other =>
-            panic!("Unexpected ExchangeOrder: {:?}", other)
-    };
+pub async fn issue_order<'a>(root_url: &'a str, api_key: &'a str, api_secret: &'a str, symbol: &'a str, order: &'a ExchangeOrder) -> Result<Vec<OrchestratorEvent>, RestError> {
+    let ExchangeOrder { cl_ord_id, ord_type, price, qty, side, stop_px, time_in_force, exec_inst, .. } = order;
+    let ord_type = ord_type.unwrap_or_else(|| panic!("Unexpected ExchangeOrder without ord_type: {:?}", order));
+    let mut url_params: Vec<(&str, Cow<'a, str>)> = vec![
+        ("symbol",      symbol.into()),
+        ("ordType",     ord_type.to_string().into()),
+        ("timeInForce", time_in_force.unwrap_or(TimeInForce::GoodTillCancel).to_string().into())];
+    // both optional for execInst=Close orders, which then close the whole position
+    if let Some(qty) = qty {
+        url_params.push(("orderQty", qty.to_string().into()));
+    }
+    if let Some(side) = side {
+        url_params.push(("side", side.to_string().into()));
+    }
+    if let Some(exec_inst) = exec_inst.as_ref().filter(|x| !x.is_empty()) {
+        url_params.push(("execInst", ExecInst::to_list_string(exec_inst).into()));
+    }
+    if ord_type.has_limit_price() {
+        url_params.push(("price", price.unwrap().to_string().into()));
+    }
+    if ord_type.is_conditional() {
+        url_params.push(("stopPx", stop_px.unwrap().to_string().into()));
+    }
+    url_params.push(("clOrdID", cl_ord_id.into()));
 
 
     let expires = (Utc::now() + Duration::seconds(100)).timestamp();
-    let url_params_str = url_params.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<String>>().join("&");
+    let url_params_str = url_params.iter().map(|(k, v)| format!("{}={}", k, urlencoding::encode(v))).collect::<Vec<String>>().join("&");  // signed as sent
     let signature = sign(&format!("POST{}{}{}", API_ORDER_PATH, expires, &url_params_str), api_secret);
 
     let client = reqwest::Client::new();
     let req = client
         .post(&format!("{}{}", root_url, API_ORDER_PATH))
-        // .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("content-type", "application/x-www-form-urlencoded")
         .header("api-expires", expires)
         .header("api-key", api_key)
         .header("api-signature", signature)
-        // .body(url_params_str);
-        .form(&url_params);
+        .body(url_params_str.clone());
 
     let res = req.send().await?;
+    match order_resp_2_orchestrator_event(res, &url_params_str).await? {
+        // not accepted, eg. invalid price, hence never to be live
+        NewStatus(status) => Ok(vec!(UpdateOrder(ExchangeOrder::with_status(cl_ord_id, OrderStatus::Rejected)), NewStatus(status))),
+        event => Ok(vec!(event))
+    }
+}
+
+/// Amend an Order in place, retaining its queue priority where the exchange allows.
+pub async fn amend_order<'a>(root_url: &'a str, api_key: &'a str, api_secret: &'a str, order: &'a ExchangeOrder) -> Result<OrchestratorEvent, RestError> {
+    let mut url_params: Vec<(&str, Cow<'a, str>)> = vec![("origClOrdID", order.cl_ord_id.as_str().into())];
+    if let Some(price) = order.price {
+        url_params.push(("price", price.to_string().into()));
+    }
+    if let Some(qty) = order.qty {
+        url_params.push(("orderQty", qty.to_string().into()));
+    }
+    if let Some(leaves_qty) = order.leaves_qty {
+        url_params.push(("leavesQty", leaves_qty.to_string().into()));
+    }
+
+    let expires = (Utc::now() + Duration::seconds(100)).timestamp();
+    let url_params_str = url_params.iter().map(|(k, v)| format!("{}={}", k, urlencoding::encode(v))).collect::<Vec<String>>().join("&");  // signed as sent
+    let signature = sign(&format!("PUT{}{}{}", API_ORDER_PATH, expires, &url_params_str), api_secret);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .put(&format!("{}{}", root_url, API_ORDER_PATH))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("api-expires", expires)
+        .header("api-key", api_key)
+        .header("api-signature", signature)
+        .body(url_params_str.clone())
+        .send()
+        .await?;
+    order_resp_2_orchestrator_event(res, &url_params_str).await
+}
+
+/// Convert http Order response to OrchestratorEvent.
+async fn order_resp_2_orchestrator_event(res: reqwest::Response, url_params_str: &str) -> Result<OrchestratorEvent, RestError> {
     match res.status() {
         StatusCode::OK => {
             let resp_body = res.text().await?;
             match serde_json::from_str::<Response>(&resp_body)? {
-                Response::Order(Order { cl_ord_id, ord_status, ord_type,  price, order_qty, side, .. }) => {
-                    Ok(
-                        UpdateOrder(ExchangeOrder {
-                            cl_ord_id,
-                            ord_status,
-                            ord_type,
-                            price: Some(price),
-                            qty: Some(order_qty),
-                            side: Some(side)
-                        }))
-                }
+                Response::Order(order) => Ok(order_2_orchestrator_event(order))
             }
         }
         status => {
@@ -80,9 +109,31 @@ pub async fn issue_order<'a>(root_url: &'a str, api_key: &'a str, api_secret: &'
     }
 }
 
-/// Cancel an Order.
-pub async fn cancel_order(root_url: &str, api_key: &str, api_secret: &str, cl_ord_id: &str) -> Result<OrchestratorEvent, RestError> {
-    let url_params = format!("clOrdID={}", cl_ord_id);
+fn order_2_orchestrator_event(order: Order) -> OrchestratorEvent {
+    let Order { cl_ord_id, ord_status, ord_type,  price, order_qty, side, cum_qty, leaves_qty, avg_px, stop_px, time_in_force, exec_inst, .. } = order;
+    UpdateOrder(ExchangeOrder {
+        cl_ord_id,
+        ord_status,
+        ord_type,
+        price,
+        qty: Some(order_qty),
+        side: Some(side),
+        cum_qty,
+        leaves_qty,
+        avg_px,
+        stop_px,
+        time_in_force,
+        exec_inst: exec_inst.as_deref().map(ExecInst::parse_list)
+    })
+}
+
+/// Cancel multiple Orders in a single request, returning the canceled Orders.
+pub async fn cancel_orders(root_url: &str, api_key: &str, api_secret: &str, cl_ord_ids: &[&str]) -> Result<Vec<OrchestratorEvent>, RestError> {
+    let cl_ord_id = match cl_ord_ids {
+        [cl_ord_id] => cl_ord_id.to_string(),
+        _ => serde_json::to_string(cl_ord_ids)?
+    };
+    let url_params = format!("clOrdID={}", urlencoding::encode(&cl_ord_id));  // signed as sent
     let expires = (Utc::now() + Duration::seconds(100)).timestamp();
     let signature = sign(&format!("DELETE{}{}{}", API_ORDER_PATH, expires, &url_params), api_secret);
 
@@ -93,11 +144,77 @@ pub async fn cancel_order(root_url: &str, api_key: &str, api_secret: &str, cl_or
         .header("api-expires", expires.to_string())
         .header("api-key", api_key)
         .header("api-signature", signature)
+        .body(url_params.clone())
+        .send()
+        .await?;
+    match res.status() {
+        StatusCode::OK => Ok(serde_json::from_str::<Vec<Order>>(&res.text().await?)?.into_iter().map(order_2_orchestrator_event).collect()),
+        status => Ok(vec!(NewStatus(format!("Received unexpected http response status {} on cancel: {:?}\nreq: {:?}", status, res.text().await?, url_params))))
+    }
+}
+
+/// Cancel all Orders of the symbol, incl. those placed elsewhere, returning the canceled Orders.
+pub async fn cancel_all_orders(root_url: &str, api_key: &str, api_secret: &str, symbol: &str) -> Result<Vec<OrchestratorEvent>, RestError> {
+    let url_params = format!("symbol={}", urlencoding::encode(symbol));
+    let expires = (Utc::now() + Duration::seconds(100)).timestamp();
+    let signature = sign(&format!("DELETE{}{}{}", API_ORDER_ALL_PATH, expires, &url_params), api_secret);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .delete(&format!("{}{}", root_url, API_ORDER_ALL_PATH))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("api-expires", expires.to_string())
+        .header("api-key", api_key)
+        .header("api-signature", signature)
         .body(url_params)
         .send()
         .await?;
+    match res.status() {
+        StatusCode::OK => Ok(serde_json::from_str::<Vec<Order>>(&res.text().await?)?.into_iter().map(order_2_orchestrator_event).collect()),
+        status => Ok(vec!(NewStatus(format!("Received unexpected http response status {} on cancel all: {:?}", status, res.text().await?))))
+    }
+}
 
-    Ok(NewStatus(res.text().await?))
+/// Arm the dead man's switch, cancelling all Orders unless re-armed within timeout, or disarm it on zero.
+pub async fn cancel_all_after(root_url: &str, api_key: &str, api_secret: &str, timeout: time::Duration) -> Result<OrchestratorEvent, RestError> {
+    let url_params = format!("timeout={}", timeout.as_millis());
+    let expires = (Utc::now() + Duration::seconds(100)).timestamp();
+    let signature = sign(&format!("POST{}{}{}", API_CANCEL_ALL_AFTER_PATH, expires, &url_params), api_secret);
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post(&format!("{}{}", root_url, API_CANCEL_ALL_AFTER_PATH))
+        .header("content-type", "application/x-www-form-urlencoded")
+        .header("api-expires", expires.to_string())
+        .header("api-key", api_key)
+        .header("api-signature", signature)
+        .body(url_params)
+        .send()
+        .await?;
+    match res.status() {
+        StatusCode::OK => Ok(UpdateDeadMansSwitch(serde_json::from_str::<CancelAllAfter>(&res.text().await?)?.cancel_time)),
+        status => Ok(NewStatus(format!("Received unexpected http response status {} on cancelAllAfter: {:?}", status, res.text().await?)))
+    }
+}
+
+/// Fetch the contract specification of the symbol, a public endpoint.
+pub async fn get_instrument(root_url: &str, symbol: &str) -> Result<OrchestratorEvent, RestError> {
+    let res = reqwest::Client::new()
+        .get(&format!("{}{}", root_url, API_INSTRUMENT_PATH))
+        .query(&[("symbol", symbol)])
+        .send()
+        .await?;
+    match res.status() {
+        StatusCode::OK => {
+            let instruments = serde_json::from_str::<Vec<Instrument>>(&res.text().await?)?;
+            match instruments.into_iter().find(|x| x.symbol == symbol) {
+                Some(Instrument { symbol, tick_size, lot_size, multiplier, is_inverse, max_order_qty }) =>
+                    Ok(NewInstrument(model::Instrument { symbol, tick_size, lot_size: lot_size.unwrap_or(Decimal::ONE), multiplier: multiplier.unwrap_or(Decimal::ONE), is_inverse, max_order_qty })),
+                None => Ok(NewStatus(format!("Unknown instrument {}, retaining configured tick size", symbol)))
+            }
+        }
+        status => Ok(NewStatus(format!("Received unexpected http response status {} for instrument {}: {:?}", status, symbol, res.text().await?)))
+    }
 }
 
 
@@ -1,6 +1,8 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
-use super::model::{OrderStatus, OrderType, Side};
+use super::model::{OrderStatus, OrderType, Side, TimeInForce};
 
 
 #[derive(Deserialize, Debug, Serialize, PartialEq)]
@@ -10,10 +12,35 @@ pub struct Order {
     pub cl_ord_id: String,
     pub symbol: String,
     pub side: Side,
-    pub order_qty: f64,
+    pub order_qty: Decimal,
     pub ord_status: OrderStatus,
     pub ord_type: Option<OrderType>,
-    pub price: f64,
+    pub price: Option<Decimal>,
+    pub stop_px: Option<Decimal>,
+    pub cum_qty: Option<Decimal>,
+    pub leaves_qty: Option<Decimal>,
+    pub avg_px: Option<Decimal>,
+    pub time_in_force: Option<TimeInForce>,
+    pub exec_inst: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Instrument {
+    pub symbol: String,
+    pub tick_size: Decimal,
+    pub lot_size: Option<Decimal>,
+    pub multiplier: Option<Decimal>,
+    pub is_inverse: bool,
+    pub max_order_qty: Option<Decimal>,
+}
+
+/// Response to cancelAllAfter, empty once disarmed.
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelAllAfter {
+    pub now: Option<DateTime<Utc>>,
+    pub cancel_time: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::model::{ExchangeOrder, Fill, Instrument, Side, State};
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+pub const MAX_EXEC_IDS: usize = 1000;  // recent enough to catch redeliveries, eg. on reconnect
+
+
+/// Pre-trade limits, each disabled when absent.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RiskLimits {
+    pub max_order_qty: Option<Decimal>,
+    pub max_notional: Option<Decimal>,      // qty * price, or just qty for inverse contracts, as quoted in USD
+    pub max_position: Option<Decimal>,      // absolute, should the order and all working orders on its side fill
+    pub price_band_pct: Option<Decimal>,    // max distance of limit prices from mid, in %
+    pub max_orders_per_sec: Option<usize>,  // of new orders and amends
+    pub max_session_loss: Option<Decimal>,  // of session PnL, as per SessionPnl, engaging the kill switch once crossed
+    #[serde(default)]
+    pub flatten_on_kill: bool,              // also flatten the position with a reduce-only market order
+}
+
+/// Session PnL of own fills, in the quote currency times the multiplier, or the base currency for inverse contracts, eg.
+/// XBT for XBTUSD. Contracts are linear with a multiplier of 1 till the instrument is known.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionPnl {
+    pub position_qty: Decimal,
+    pub avg_entry_value: Decimal,  // price * multiplier, or -1/price for inverse contracts, as PnL is linear in either
+    pub realised_pnl: Decimal,
+}
+
+impl SessionPnl {
+    pub fn on_fill(&mut self, side: Side, qty: Decimal, price: Decimal, instrument: Option<&Instrument>) {
+        let value = value(price, instrument);
+        let signed_qty = if side == Side::Buy { qty } else { -qty };
+        if self.position_qty.is_zero() || self.position_qty.is_sign_negative() == signed_qty.is_sign_negative() {
+            self.avg_entry_value = (self.avg_entry_value * self.position_qty.abs() + value * qty) / (self.position_qty.abs() + qty);
+        } else {
+            let closing_qty = self.position_qty.abs().min(qty);
+            let direction = if self.position_qty.is_sign_negative() { Decimal::NEGATIVE_ONE } else { Decimal::ONE };
+            self.realised_pnl += closing_qty * (value - self.avg_entry_value) * direction;
+            if qty > closing_qty {
+                self.avg_entry_value = value;  // flipped
+            }
+        }
+        self.position_qty += signed_qty;
+    }
+
+    pub fn unrealised_pnl(&self, mark_price: Decimal, instrument: Option<&Instrument>) -> Decimal {
+        self.position_qty * (value(mark_price, instrument) - self.avg_entry_value)
+    }
+}
+
+/// Risk limits, with the send times of recent orders and amends for rate limiting, and the session PnL for the kill
+/// switch.
+#[derive(Debug, Default, PartialEq)]
+pub struct RiskEngine {
+    pub limits: RiskLimits,
+    pub pnl: SessionPnl,
+    pub is_locked: bool,  // by the kill switch, till manually unlocked
+    pnl_baseline: Decimal,  // session PnL as of the last unlock
+    sent_at: VecDeque<Instant>,
+    exec_ids: VecDeque<String>,  // of the most recent fills accounted for in the PnL, up to MAX_EXEC_IDS
+}
+
+impl RiskEngine {
+    pub fn new(limits: RiskLimits) -> Self {
+        RiskEngine { limits, ..RiskEngine::default() }
+    }
+
+    /// Realised plus unrealised PnL since the session start, or the last unlock. Positions are only marked once the mark
+    /// price is known.
+    pub fn session_pnl(&self, mark_price: Option<Decimal>, instrument: Option<&Instrument>) -> Decimal {
+        let unrealised_pnl = mark_price.filter(|x| *x > Decimal::ZERO).map_or(Decimal::ZERO, |x| self.pnl.unrealised_pnl(x, instrument));
+        self.pnl.realised_pnl + unrealised_pnl - self.pnl_baseline
+    }
+
+    /// Account for an own fill in the session PnL, once per execution as these may be redelivered.
+    pub fn on_fill(&mut self, fill: &Fill, instrument: Option<&Instrument>) {
+        if self.exec_ids.contains(&fill.exec_id) {
+            return
+        }
+        if self.exec_ids.len() == MAX_EXEC_IDS {
+            self.exec_ids.pop_front();
+        }
+        self.exec_ids.push_back(fill.exec_id.clone());
+        self.pnl.on_fill(fill.side, fill.last_qty, fill.last_px, instrument);
+    }
+
+    /// Whether the kill switch is to engage, ie. it isn't already and the session loss limit is crossed.
+    pub fn is_loss_limit_crossed(&self, session_pnl: Decimal) -> bool {
+        !self.is_locked && self.limits.max_session_loss.is_some_and(|x| session_pnl <= -x)
+    }
+
+    /// Lift the kill switch lockout, the loss limit applying afresh from the current session PnL.
+    pub fn unlock(&mut self, session_pnl: Decimal) {
+        self.is_locked = false;
+        self.pnl_baseline += session_pnl;
+    }
+
+    /// Check a new order against all limits, given the state it is issued from.
+    pub fn check_order(&self, order: &ExchangeOrder, state: &State, now: Instant) -> Result<(), String> {
+        let side = order.side.unwrap_or(Side::Buy);
+        let qty = order.qty.unwrap_or(Decimal::ZERO);
+        let price = order.price.or(order.stop_px).unwrap_or_else(|| mid(state));
+        self.check_rate(now)
+            .and_then(|_| match self.limits.max_order_qty {
+                Some(max_order_qty) if qty > max_order_qty => Err(format!("qty above max order qty {}", max_order_qty)),
+                _ => Ok(())
+            })
+            .and_then(|_| {
+                let notional = if state.is_inverse() { qty } else { qty * price };
+                match self.limits.max_notional {
+                    Some(max_notional) if notional > max_notional => Err(format!("notional {} above max notional {}", notional, max_notional)),
+                    _ => Ok(())
+                }
+            })
+            .and_then(|_| {
+                let signed = |side: Side, qty: Decimal| if side == Side::Buy { qty } else { -qty };
+                let position_qty = state.position.as_ref().and_then(|x| x.current_qty).unwrap_or(Decimal::ZERO);
+                let working_qty = state.live_orders()
+                    .filter(|x| x.side == Some(side))
+                    .map(|x| x.leaves_qty.or(x.qty).unwrap_or(Decimal::ZERO))
+                    .sum::<Decimal>();
+                let resulting_qty = position_qty + signed(side, working_qty + qty);
+                // orders reducing the position are always let through
+                match self.limits.max_position {
+                    Some(max_position) if resulting_qty.abs() > max_position && resulting_qty.abs() > position_qty.abs() =>
+                        Err(format!("resulting position {} above max position {}", resulting_qty, max_position)),
+                    _ => Ok(())
+                }
+            })
+            .and_then(|_| match order.ord_type.filter(|x| x.has_limit_price()).and(order.price) {
+                Some(price) => self.check_price_band(price, state),
+                None => Ok(())
+            })
+            .map_err(|err| format!("Risk rejected {} of {} @ {}: {}!", side.to_string().to_lowercase(), qty, price, err))
+    }
+
+    /// Check an amend of the limit price against the price band and order rate.
+    pub fn check_amend(&self, cl_ord_id: &str, price: Decimal, state: &State, now: Instant) -> Result<(), String> {
+        self.check_rate(now)
+            .and_then(|_| self.check_price_band(price, state))
+            .map_err(|err| format!("Risk rejected amend of {} to {}: {}!", cl_ord_id, price, err))
+    }
+
+    /// Account for an order or amend sent at now, towards the order rate.
+    pub fn on_sent(&mut self, now: Instant) {
+        while self.sent_at.front().is_some_and(|x| now.duration_since(*x) >= RATE_WINDOW) {
+            self.sent_at.pop_front();
+        }
+        self.sent_at.push_back(now);
+    }
+
+    fn check_rate(&self, now: Instant) -> Result<(), String> {
+        let recent_count = self.sent_at.iter().filter(|x| now.duration_since(**x) < RATE_WINDOW).count();
+        match self.limits.max_orders_per_sec {
+            Some(max_orders_per_sec) if recent_count >= max_orders_per_sec => Err(format!("above max {} orders/sec", max_orders_per_sec)),
+            _ => Ok(())
+        }
+    }
+
+    fn check_price_band(&self, price: Decimal, state: &State) -> Result<(), String> {
+        let mid = mid(state);
+        match self.limits.price_band_pct {
+            Some(price_band_pct) if mid > Decimal::ZERO && (price - mid).abs() / mid * Decimal::ONE_HUNDRED > price_band_pct =>
+                Err(format!("price more than {}% from mid {}", price_band_pct, mid)),
+            _ => Ok(())
+        }
+    }
+}
+
+fn value(price: Decimal, instrument: Option<&Instrument>) -> Decimal {
+    match instrument {
+        Some(x) if x.is_inverse => -Decimal::ONE / price,
+        Some(x) => price * x.multiplier,
+        None => price
+    }
+}
+
+fn mid(state: &State) -> Decimal {
+    ((state.bid + state.ask) / Decimal::TWO).normalize()
+}
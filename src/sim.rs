@@ -0,0 +1,306 @@
+use rust_decimal::Decimal;
+use rust_decimal::prelude::Signed;
+use uuid::Uuid;
+
+use crate::model::{ExchangeCmd, ExchangeOrder, ExecInst, Fill, Level, OrchestratorEvent, OrchestratorEvent::*, OrderStatus, OrderType, Position, Side, Trade};
+
+
+/// Paper trading exchange, accepting the same ExchangeCmd's as the REST api, and matching them against the live
+/// orderBook10/trade feed. Responds with UpdateOrder/NewFill/UpdatePosition events, as the order/execution/position
+/// tables would.
+///
+/// Matching assumptions:
+/// - marketable orders walk the book, market orders exhausting the book fill the remainder at the last level
+/// - resting limits join the back of the queue, filling only once trades at their price exceed the size ahead,
+///   with size ahead shrinking along with the level (ie. cancels ahead of us)
+/// - resting limits fill at their price once traded or quoted through
+/// - conditional orders trigger on the last trade price
+#[derive(Debug, Default)]
+pub struct SimExchange {
+    orders: Vec<SimOrder>,  // working, in order of arrival
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+    position_qty: Decimal,
+    avg_entry_price: Decimal,
+    realised_pnl: Decimal,
+    fills: Vec<SimFill>,
+}
+
+/// Fill, with whether liquidity was provided, eg. for fee calculation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimFill {
+    pub cl_ord_id: String,
+    pub side: Side,
+    pub qty: Decimal,
+    pub price: Decimal,
+    pub is_maker: bool,
+}
+
+#[derive(Debug)]
+struct SimOrder {
+    order: ExchangeOrder,
+    queue_ahead: Decimal,
+    is_triggered: bool,
+}
+
+impl SimExchange {
+    pub fn new() -> Self {
+        SimExchange::default()
+    }
+
+    /// Fills so far, in order.
+    pub fn fills(&self) -> &[SimFill] {
+        &self.fills
+    }
+
+    /// Execute an ExchangeCmd, as would the REST api.
+    pub fn handle_cmd(&mut self, cmd: &ExchangeCmd) -> Vec<OrchestratorEvent> {
+        match cmd {
+            ExchangeCmd::IssueOrder(order) => self.issue(order),
+            ExchangeCmd::CancelOrder(cl_ord_id) => self.cancel(cl_ord_id),
+            ExchangeCmd::CancelOrders(cl_ord_ids) => cl_ord_ids.iter().flat_map(|x| self.cancel(x)).collect(),
+            ExchangeCmd::CancelAllOrders => self.cancel_all(),
+            ExchangeCmd::AmendOrder(order) => self.amend(order),
+            ExchangeCmd::CancelAllAndClose(close_order) => {
+                let mut events = self.cancel_all();
+                events.extend(self.issue(close_order));
+                events
+            }
+            ExchangeCmd::Batch(cmds) => cmds.iter().flat_map(|x| self.handle_cmd(x)).collect(),
+            ExchangeCmd::CancelAllAfter(_) => Vec::new(),  // the sim never loses its connection
+        }
+    }
+
+    /// Match working orders against market data events, other events are ignored.
+    pub fn on_market_event(&mut self, event: &OrchestratorEvent) -> Vec<OrchestratorEvent> {
+        match event {
+            NewDepth(bids, asks) => {
+                self.bids = bids.clone();
+                self.asks = asks.clone();
+                self.match_book()
+            }
+            NewTrades(trades) => trades.iter().flat_map(|x| self.match_trade(x)).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Cancel all working orders.
+    pub fn cancel_all(&mut self) -> Vec<OrchestratorEvent> {
+        let cl_ord_ids = self.orders.iter().map(|x| x.order.cl_ord_id.clone()).collect::<Vec<String>>();
+        cl_ord_ids.iter().flat_map(|x| self.cancel(x)).collect()
+    }
+
+    fn issue(&mut self, order: &ExchangeOrder) -> Vec<OrchestratorEvent> {
+        // Close orders sans qty close the whole position
+        let is_close = order.exec_inst.as_ref().is_some_and(|x| x.contains(&ExecInst::Close));
+        let order = match order.qty {
+            None if is_close && self.position_qty.is_zero() =>
+                return vec!(UpdateOrder(ExchangeOrder { ord_status: OrderStatus::Rejected, .. order.clone() }), NewStatus(format!("Sim: rejected close order {}, no position", order.cl_ord_id))),
+            None if is_close => {
+                let side = if self.position_qty > Decimal::ZERO { Side::Sell } else { Side::Buy };
+                ExchangeOrder { side: Some(side), qty: Some(self.position_qty.abs()), .. order.clone() }
+            }
+            _ => order.clone()
+        };
+        let order = ExchangeOrder { ord_status: OrderStatus::New, cum_qty: Some(Decimal::ZERO), leaves_qty: order.qty, avg_px: None, .. order };
+        let is_conditional = order.ord_type.is_some_and(|x| x.is_conditional());
+        let mut events = vec!(UpdateOrder(order.clone()));
+        self.orders.push(SimOrder { order, queue_ahead: Decimal::ZERO, is_triggered: false });
+        if !is_conditional {
+            events.extend(self.activate(self.orders.len() - 1));
+        }
+        self.retain_working();
+        events
+    }
+
+    fn cancel(&mut self, cl_ord_id: &str) -> Vec<OrchestratorEvent> {
+        match self.orders.iter_mut().find(|x| x.order.cl_ord_id == cl_ord_id) {
+            Some(sim_order) => {
+                sim_order.order.ord_status = OrderStatus::Canceled;
+                let event = UpdateOrder(sim_order.order.clone());
+                self.retain_working();
+                vec!(event)
+            }
+            None => vec!(NewStatus(format!("Sim: no working order {} to cancel", cl_ord_id)))
+        }
+    }
+
+    fn amend(&mut self, amendment: &ExchangeOrder) -> Vec<OrchestratorEvent> {
+        let ind = match self.orders.iter().position(|x| x.order.cl_ord_id == amendment.cl_ord_id) {
+            Some(ind) => ind,
+            None => return vec!(NewStatus(format!("Sim: no working order {} to amend", amendment.cl_ord_id)))
+        };
+        let order = &mut self.orders[ind].order;
+        order.price = amendment.price.or(order.price);
+        order.stop_px = amendment.stop_px.or(order.stop_px);
+        if let Some(qty) = amendment.qty {
+            order.qty = Some(qty);
+            order.leaves_qty = Some(qty - order.cum_qty.unwrap_or(Decimal::ZERO));
+        }
+        let mut events = vec!(UpdateOrder(order.clone()));
+        let sim_order = &self.orders[ind];
+        if !sim_order.order.ord_type.is_some_and(|x| x.is_conditional()) || sim_order.is_triggered {
+            // loses queue priority
+            events.extend(self.activate(ind));
+        }
+        self.retain_working();
+        events
+    }
+
+    /// Take liquidity if marketable, rest the remainder at the back of the queue.
+    fn activate(&mut self, ind: usize) -> Vec<OrchestratorEvent> {
+        let order = &self.orders[ind].order;
+        let side = order.side.unwrap();
+        let ord_type = order.ord_type.unwrap();
+        let limit_price = if ord_type.has_limit_price() { order.price } else { None };
+        let is_post_only = order.exec_inst.as_ref().is_some_and(|x| x.contains(&ExecInst::ParticipateDoNotInitiate));
+        let opposite_levels = match side { Side::Buy => self.asks.clone(), Side::Sell => self.bids.clone() };
+        let is_marketable = |price: Decimal| limit_price.is_none_or(|limit| if side == Side::Buy { price <= limit } else { price >= limit });
+
+        if is_post_only && opposite_levels.first().is_some_and(|x| is_marketable(x.price)) {
+            self.orders[ind].order.ord_status = OrderStatus::Canceled;
+            return vec!(UpdateOrder(self.orders[ind].order.clone()), NewStatus(format!("Sim: canceled post only order {} as it would take liquidity", self.orders[ind].order.cl_ord_id)));
+        }
+
+        let mut events = Vec::new();
+        for level in opposite_levels.iter().take_while(|x| is_marketable(x.price)) {
+            let leaves_qty = self.orders[ind].order.leaves_qty.unwrap_or(Decimal::ZERO);
+            if leaves_qty <= Decimal::ZERO {
+                break
+            }
+            events.extend(self.fill(ind, leaves_qty.min(level.size), level.price, false));
+        }
+
+        let leaves_qty = self.orders[ind].order.leaves_qty.unwrap_or(Decimal::ZERO);
+        if leaves_qty > Decimal::ZERO {
+            match (limit_price, opposite_levels.last()) {
+                (Some(price), _) => {
+                    let same_side_levels = match side { Side::Buy => &self.bids, Side::Sell => &self.asks };
+                    self.orders[ind].queue_ahead = same_side_levels.iter().find(|x| x.price == price).map_or(Decimal::ZERO, |x| x.size);
+                }
+                (None, Some(last_level)) =>
+                    events.extend(self.fill(ind, leaves_qty, last_level.price, false)),
+                (None, None) => {
+                    self.orders[ind].order.ord_status = OrderStatus::Canceled;
+                    events.push(UpdateOrder(self.orders[ind].order.clone()));
+                    events.push(NewStatus(format!("Sim: canceled market order {} as the book is empty", self.orders[ind].order.cl_ord_id)));
+                }
+            }
+        }
+        events
+    }
+
+    /// Fill resting limits quoted through, and shrink the queue ahead with the level.
+    fn match_book(&mut self) -> Vec<OrchestratorEvent> {
+        let mut events = Vec::new();
+        for ind in 0..self.orders.len() {
+            let sim_order = &self.orders[ind];
+            let (side, price) = match (sim_order.order.side, sim_order.order.price, sim_order.order.ord_type) {
+                (Some(side), Some(price), Some(ord_type)) if ord_type.has_limit_price() && (!ord_type.is_conditional() || sim_order.is_triggered) => (side, price),
+                _ => continue
+            };
+            let (same_side_levels, opposite_levels) = match side { Side::Buy => (&self.bids, &self.asks), Side::Sell => (&self.asks, &self.bids) };
+            let is_better_or_eq = |x: Decimal, y: Decimal| if side == Side::Buy { x >= y } else { x <= y };
+            if opposite_levels.first().is_some_and(|x| is_better_or_eq(price, x.price)) {
+                let leaves_qty = sim_order.order.leaves_qty.unwrap_or(Decimal::ZERO);
+                events.extend(self.fill(ind, leaves_qty, price, true));
+            } else if let Some(level) = same_side_levels.iter().find(|x| x.price == price) {
+                self.orders[ind].queue_ahead = self.orders[ind].queue_ahead.min(level.size);
+            } else if same_side_levels.first().is_none_or(|x| is_better_or_eq(price, x.price)) {
+                self.orders[ind].queue_ahead = Decimal::ZERO;
+            }
+        }
+        self.retain_working();
+        events
+    }
+
+    /// Trigger conditional orders, and fill resting limits traded at or through.
+    fn match_trade(&mut self, trade: &Trade) -> Vec<OrchestratorEvent> {
+        let mut events = Vec::new();
+        let mut trade_size_left = trade.size;
+        for ind in 0..self.orders.len() {
+            let sim_order = &self.orders[ind];
+            let (side, ord_type) = (sim_order.order.side.unwrap(), sim_order.order.ord_type.unwrap());
+            if ord_type.is_conditional() && !sim_order.is_triggered {
+                let stop_px = sim_order.order.stop_px.unwrap();
+                let is_triggered = match (ord_type, side) {
+                    (OrderType::Stop, Side::Buy) | (OrderType::StopLimit, Side::Buy) | (OrderType::MarketIfTouched, Side::Sell) | (OrderType::LimitIfTouched, Side::Sell) => trade.price >= stop_px,
+                    _ => trade.price <= stop_px,
+                };
+                if is_triggered {
+                    events.push(NewStatus(format!("Sim: triggered {} {} order {} @ {}", side, ord_type, sim_order.order.cl_ord_id, trade.price)));
+                    self.orders[ind].is_triggered = true;
+                    events.extend(self.activate(ind));
+                }
+                continue
+            }
+            let price = match sim_order.order.price {
+                Some(price) if ord_type.has_limit_price() => price,
+                _ => continue
+            };
+            // aggressive sells trade against bids, aggressive buys against asks
+            let is_traded_through = match (side, trade.side) {
+                (Side::Buy, Side::Sell) => trade.price < price,
+                (Side::Sell, Side::Buy) => trade.price > price,
+                _ => continue
+            };
+            let leaves_qty = sim_order.order.leaves_qty.unwrap_or(Decimal::ZERO);
+            if is_traded_through {
+                events.extend(self.fill(ind, leaves_qty, price, true));
+            } else if trade.price == price {
+                let queue_ahead = self.orders[ind].queue_ahead;
+                let fill_qty = (trade_size_left - queue_ahead).max(Decimal::ZERO).min(leaves_qty);
+                self.orders[ind].queue_ahead = (queue_ahead - trade_size_left).max(Decimal::ZERO);
+                trade_size_left -= queue_ahead.min(trade_size_left) + fill_qty;
+                if fill_qty > Decimal::ZERO {
+                    events.extend(self.fill(ind, fill_qty, price, true));
+                }
+            }
+        }
+        self.retain_working();
+        events
+    }
+
+    fn fill(&mut self, ind: usize, qty: Decimal, price: Decimal, is_maker: bool) -> Vec<OrchestratorEvent> {
+        let order = &mut self.orders[ind].order;
+        let prev_cum_qty = order.cum_qty.unwrap_or(Decimal::ZERO);
+        let cum_qty = prev_cum_qty + qty;
+        let leaves_qty = (order.qty.unwrap_or(Decimal::ZERO) - cum_qty).max(Decimal::ZERO);
+        order.avg_px = Some((order.avg_px.unwrap_or(Decimal::ZERO) * prev_cum_qty + price * qty) / cum_qty);
+        order.cum_qty = Some(cum_qty);
+        order.leaves_qty = Some(leaves_qty);
+        order.ord_status = if leaves_qty > Decimal::ZERO { OrderStatus::PartiallyFilled } else { OrderStatus::Filled };
+        let side = order.side.unwrap();
+        let fill = NewFill(Fill { cl_ord_id: order.cl_ord_id.clone(), exec_id: Uuid::new_v4().to_string(), side, last_qty: qty, last_px: price, cum_qty: Some(cum_qty) });
+        let update = UpdateOrder(order.clone());
+        self.fills.push(SimFill { cl_ord_id: order.cl_ord_id.clone(), side, qty, price, is_maker });
+        vec!(fill, update, self.update_position(side, qty, price))
+    }
+
+    fn update_position(&mut self, side: Side, qty: Decimal, price: Decimal) -> OrchestratorEvent {
+        let signed_qty = if side == Side::Buy { qty } else { -qty };
+        if self.position_qty == Decimal::ZERO || self.position_qty.signum() == signed_qty.signum() {
+            self.avg_entry_price = (self.avg_entry_price * self.position_qty.abs() + price * qty) / (self.position_qty.abs() + qty);
+        } else {
+            let closing_qty = self.position_qty.abs().min(qty);
+            self.realised_pnl += closing_qty * (price - self.avg_entry_price) * self.position_qty.signum();
+            if qty > closing_qty {
+                self.avg_entry_price = price;  // flipped
+            }
+        }
+        self.position_qty += signed_qty;
+        if self.position_qty == Decimal::ZERO {
+            self.avg_entry_price = Decimal::ZERO;
+        }
+        UpdatePosition(Position {
+            current_qty:     Some(self.position_qty),
+            avg_entry_price: Some(self.avg_entry_price),
+            realised_pnl:    Some(self.realised_pnl),
+            ..Position::default()
+        })
+    }
+
+    fn retain_working(&mut self) {
+        self.orders.retain(|x| x.order.is_cancelable());
+    }
+}
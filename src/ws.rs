@@ -1,44 +1,206 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::io;
 use std::sync::mpsc;
+use std::time;
 
 use chrono::{Duration, Utc};
-use tungstenite::{connect, Message};
+use rust_decimal::Decimal;
+use thiserror::Error;
+use tungstenite::{connect, Message, WebSocket};
+use tungstenite::client::AutoStream;
+use tungstenite::stream::Stream;
 
-use crate::model::{ExchangeOrder, OrchestratorEvent, OrchestratorEvent::*};
+use crate::model;
+use crate::model::{ConnectionState, ExchangeOrder, ExecInst, Fill, Level, OrchestratorEvent, OrchestratorEvent::*, Side};
+use crate::order_book::OrderBook;
+use crate::recorder::{Recorder, RecordingConfig};
 use crate::sign::sign;
-use crate::ws_model::{Request, Response, Response::*, Table::*};
+use crate::ws_model::{OrderBookL2Row, Request, Response, Response::*, Table::*, TableAction};
 
+const INITIAL_BACKOFF: time::Duration = time::Duration::from_millis(500);
+const MAX_BACKOFF: time::Duration = time::Duration::from_secs(30);
+const DEPTH_LEVELS: usize = 10;  // as per orderBook10
 
-/// Listen to WS messages and push them to supplied channel.
-pub fn handle_msgs(url: &str, api_key: &str, api_secret: &str, subscriptions: Vec<String>, tx: mpsc::Sender<OrchestratorEvent>) {
+type WsSocket = WebSocket<AutoStream>;
+
+/// BitMEX recommended keep alive, pinging when no message has arrived within interval, and expecting a pong within timeout.
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: time::Duration,
+    pub timeout: time::Duration,
+}
+
+
+/// Listen to WS messages and push them to supplied channel, reconnecting with exponential backoff on connection loss.
+/// Text frames are recorded, if configured. Returns once the channel is closed, or on a stop signal, or once the stop
+/// sender is dropped, finishing the recording.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_msgs(url: &str, api_key: &str, api_secret: &str, subscriptions: Vec<String>, heartbeat: Heartbeat, recording: Option<RecordingConfig>, tx: mpsc::Sender<OrchestratorEvent>, stop_rx: mpsc::Receiver<()>) {
+    let mut recorder = recording.and_then(|x| Recorder::new(x).map_err(|err| log::error!("Failed to start ws recording: {}", err)).ok());
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        if tx.send(NewConnectionState(ConnectionState::Connecting)).is_err() {
+            break
+        }
+        let res = connect_and_subscribe(url, api_key, api_secret, &subscriptions).and_then(|mut ws_socket| {
+            tx.send(NewConnectionState(ConnectionState::Connected))?;
+            backoff = INITIAL_BACKOFF;
+            read_msgs(&mut ws_socket, heartbeat, &mut recorder, &tx, &stop_rx)
+        });
+        match res {
+            Err(WsError::ChannelClosed) | Err(WsError::Stopped) => break,
+            Err(err) => log::error!("ws connection to {} failed, reconnecting in {:?}: {}", url, backoff, err),
+            Ok(()) => log::warn!("ws connection to {} closed, reconnecting in {:?}", url, backoff),
+        }
+        if tx.send(NewConnectionState(ConnectionState::Disconnected)).is_err() {
+            break
+        }
+        if stop_rx.recv_timeout(backoff) != Err(mpsc::RecvTimeoutError::Timeout) {
+            break
+        }
+        backoff = min(backoff * 2, MAX_BACKOFF);
+    }
+    if let Some(Err(err)) = recorder.map(Recorder::finish) {
+        log::error!("Failed to finish ws recording: {}", err);
+    }
+}
+
+/// Connect, authenticate and subscribe.
+fn connect_and_subscribe(url: &str, api_key: &str, api_secret: &str, subscriptions: &[String]) -> Result<WsSocket, WsError> {
     let expires = (Utc::now() + Duration::seconds(100)).timestamp();
     let signature = sign(&format!("GET/realtime{}", expires), api_secret);
     let authenticate = Request::Authenticate(api_key.to_string(), expires, signature);
-    let (mut ws_socket, _) = connect(url).unwrap_or_else(|_| panic!("Failed to connect to ws_url {}", url));
-    let subscribe = Request::Subscribe(subscriptions);
-    ws_socket.write_message(Message::text(serde_json::to_string(&authenticate).unwrap_or_else(|_| panic!("Failed to parse authenticate event {:?}", &authenticate)))).unwrap_or_else(|_| panic!("Failed to send authenticate event {:?}", &authenticate));
-    ws_socket.write_message(Message::text(serde_json::to_string(&subscribe).unwrap_or_else(|_| panic!("Failed to parse subscribe event {:?}", &subscribe)))).unwrap_or_else(|_| panic!("Failed to send subscribe event {:?}", &subscribe));
+    let subscribe = Request::Subscribe(subscriptions.to_vec());
+    let (mut ws_socket, _) = connect(url)?;
+    ws_socket.write_message(Message::text(serde_json::to_string(&authenticate)?))?;
+    ws_socket.write_message(Message::text(serde_json::to_string(&subscribe)?))?;
+    Ok(ws_socket)
+}
 
+/// Read messages till the connection is closed, or deemed dead by the heartbeat, or stopped. The stop signal is checked
+/// between messages, ie. at least every heartbeat interval.
+fn read_msgs(ws_socket: &mut WsSocket, heartbeat: Heartbeat, recorder: &mut Option<Recorder>, tx: &mpsc::Sender<OrchestratorEvent>, stop_rx: &mpsc::Receiver<()>) -> Result<(), WsError> {
+    set_read_timeout(ws_socket, min(heartbeat.interval, heartbeat.timeout))?;
+    let mut last_msg_at = time::Instant::now();
+    let mut ping_sent_at: Option<time::Instant> = None;
+    let mut decoder = FeedDecoder::new();  // order books rebuilt from partial on each connection
     loop {
-        let msg= ws_socket.read_message().expect("Failed to read ws message");
+        if stop_rx.try_recv() != Err(mpsc::TryRecvError::Empty) {
+            ws_socket.close(None).ok();
+            return Err(WsError::Stopped)
+        }
+        let msg = match ws_socket.read_message() {
+            Ok(msg) => msg,
+            Err(tungstenite::Error::Io(ref err)) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {
+                match ping_sent_at {
+                    None if last_msg_at.elapsed() >= heartbeat.interval => {
+                        log::info!("No ws message for {:?}, pinging", last_msg_at.elapsed());
+                        ws_socket.write_message(Message::text("ping"))?;
+                        ping_sent_at = Some(time::Instant::now());
+                        tx.send(NewConnectionState(ConnectionState::Stale))?;
+                    }
+                    Some(sent_at) if sent_at.elapsed() >= heartbeat.timeout =>
+                        return Err(WsError::HeartbeatTimeout(sent_at.elapsed())),
+                    _ => ()
+                }
+                continue
+            }
+            Err(err) => return Err(err.into())
+        };
+        last_msg_at = time::Instant::now();
+        if let (Some(rec), Message::Text(ref payload)) = (recorder.as_mut(), &msg) {
+            if let Err(err) = rec.record(Utc::now(), payload) {
+                log::error!("Failed to record ws frame, stopping recording: {}", err);
+                *recorder = None;
+            }
+        }
+        if ping_sent_at.take().is_some() {
+            tx.send(NewConnectionState(ConnectionState::Connected))?;
+        }
         match msg {
+            Message::Text(ref payload) if payload == "pong" => {}
             Message::Text(ref payload) => {
-                match serde_json::from_str::<Response>(&payload) {
-                    Ok(ws_resp) =>
-                        for x in ws_resp_2_orchestrator_event(&ws_resp) {
-                            tx.send(x).expect("Failed to ws send");  // FIXME: how to pass x to err msg?
+                match serde_json::from_str::<Response>(payload) {
+                    Ok(ws_resp) => {
+                        let (events, resync_topic) = decoder.decode(&ws_resp);
+                        for x in events {
+                            tx.send(x)?;
+                        }
+                        if let Some(topic) = resync_topic {
+                            ws_socket.write_message(Message::text(serde_json::to_string(&Request::Unsubscribe(vec!(topic.clone())))?))?;
+                            ws_socket.write_message(Message::text(serde_json::to_string(&Request::Subscribe(vec!(topic)))?))?;
                         }
+                    }
                     Err(err) =>
                         log::error!("channel error {} on payload {}", err, &payload),
                 }
             }
             Message::Binary(_) | Message::Ping(_) | Message::Pong(_) => {}
-            Message::Close(_) => break
+            Message::Close(_) => return Ok(())
+        }
+    }
+}
+
+/// Decodes WS responses into OrchestratorEvent's, maintaining the L2 order books per topic. Shared by the live feed
+/// and replays.
+#[derive(Debug, Default)]
+pub struct FeedDecoder {
+    order_books: HashMap<String, OrderBook>,  // per topic, rebuilt from partial
+}
+
+impl FeedDecoder {
+    pub fn new() -> Self {
+        FeedDecoder::default()
+    }
+
+    /// Decode a response, returning its events, and the topic to resubscribe to on order book inconsistency.
+    pub fn decode(&mut self, resp: &Response) -> (Vec<OrchestratorEvent>, Option<String>) {
+        match resp {
+            Table(OrderBookL2 { ref action, ref data }) => self.apply_order_book("orderBookL2", action, data),
+            Table(OrderBookL2_25 { ref action, ref data }) => self.apply_order_book("orderBookL2_25", action, data),
+            _ => (ws_resp_2_orchestrator_event(resp), None)
+        }
+    }
+
+    /// Apply L2 table action to the topic's order book, returning the resulting top of book.
+    /// On inconsistency, the topic is returned for resubscription, to receive a fresh partial.
+    fn apply_order_book(&mut self, table: &str, action: &TableAction, data: &[OrderBookL2Row]) -> (Vec<OrchestratorEvent>, Option<String>) {
+        let topic = match data.first() {
+            Some(row) => format!("{}:{}", table, row.symbol),
+            None => return (vec!(), None)
+        };
+        let order_book = self.order_books.entry(topic.clone()).or_default();
+        match order_book.apply(action, data) {
+            Ok(()) => {
+                let mut events = Vec::new();
+                if let Some(ask) = order_book.best_ask() {
+                    events.push(NewAsk(ask.price));
+                }
+                if let Some(bid) = order_book.best_bid() {
+                    events.push(NewBid(bid.price));
+                }
+                events.push(NewDepth(order_book.depth(Side::Buy, DEPTH_LEVELS), order_book.depth(Side::Sell, DEPTH_LEVELS)));
+                (events, None)
+            }
+            Err(err) => {
+                log::warn!("order book {} inconsistent on {} of {:?}: {}, requesting new partial", topic, action, data, err);
+                (vec!(NewStatus(format!("Order book {} inconsistent: {}, resyncing", topic, err))), Some(topic))
+            }
         }
     }
 }
 
+/// Set read timeout on the underlying tcp stream, so that reads don't block past the heartbeat.
+fn set_read_timeout(ws_socket: &mut WsSocket, timeout: time::Duration) -> io::Result<()> {
+    match ws_socket.get_mut() {
+        Stream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        Stream::Tls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+    }
+}
+
 /// Convert WS Response to OrchestratorEvent
-fn ws_resp_2_orchestrator_event(resp: &Response) -> Vec<OrchestratorEvent> {
+pub fn ws_resp_2_orchestrator_event(resp: &Response) -> Vec<OrchestratorEvent> {
     match resp {
         Subscribe { subscribe, success } =>
             vec!(NewStatus(format!("Subscribed to {}: {}", subscribe, success))),
@@ -47,20 +209,60 @@ fn ws_resp_2_orchestrator_event(resp: &Response) -> Vec<OrchestratorEvent> {
         Error { error, .. } =>
             vec!(NewStatus(format!("Error on: {:?}", error))),
         Table(OrderBook10{ ref data, .. }) => {
-            let mut events1 = data.first().iter().map(|x| NewAsk(x.first_ask())).collect::<Vec<OrchestratorEvent>>();
-            let mut events2 = data.first().iter().map(|x| NewBid(x.first_bid())).collect::<Vec<OrchestratorEvent>>();
+            let mut events1 = data.first().and_then(|x| x.first_ask()).into_iter().map(NewAsk).collect::<Vec<OrchestratorEvent>>();
+            let mut events2 = data.first().and_then(|x| x.first_bid()).into_iter().map(NewBid).collect::<Vec<OrchestratorEvent>>();
+            let mut events3 = data.first().iter().map(|x| NewDepth(
+                x.bids.iter().map(|[price, size]| Level { price: *price, size: *size }).collect(),
+                x.asks.iter().map(|[price, size]| Level { price: *price, size: *size }).collect())).collect::<Vec<OrchestratorEvent>>();
             events1.append(&mut events2);
+            events1.append(&mut events3);
             events1
         },
-        Table(Order{ ref data, .. }) =>
-            data.iter().map(|x|
+        Table(Trade{ ref data, .. }) =>
+            vec!(NewTrades(data.iter().map(|x| model::Trade { timestamp: x.timestamp, side: x.side, size: x.size, price: x.price, tick_direction: x.tick_direction }).collect())),
+        Table(Order{ ref action, ref data }) => {
+            // partial is the full snapshot of open orders, eg. post reconnect
+            let sync_events = if *action == TableAction::Partial {
+                vec!(SyncLiveOrders(data.iter().map(|x| x.cl_ord_id.to_string()).collect()))
+            } else {
+                vec!()
+            };
+            sync_events.into_iter().chain(data.iter().map(|x|
                 UpdateOrder(ExchangeOrder {
                     cl_ord_id:  x.cl_ord_id.to_string(),
                     ord_status: x.ord_status,
                     ord_type:   x.ord_type,
                     price:      x.price,
                     qty:        x.order_qty,
-                    side:       x.side
+                    side:       x.side,
+                    cum_qty:    x.cum_qty,
+                    leaves_qty: x.leaves_qty,
+                    avg_px:     x.avg_px,
+                    stop_px:    x.stop_px,
+                    time_in_force: x.time_in_force,
+                    exec_inst:  x.exec_inst.as_deref().map(ExecInst::parse_list)
+                }))).collect()
+        }
+        Table(Execution{ ref data, .. }) =>
+            data.iter().filter(|x| x.exec_type == "Trade").filter_map(|x| Some(
+                NewFill(Fill {
+                    cl_ord_id: x.cl_ord_id.to_string(),
+                    exec_id:   x.exec_id.to_string(),
+                    side:      x.side?,
+                    last_qty:  x.last_qty.unwrap_or(Decimal::ZERO),
+                    last_px:   x.last_px.unwrap_or(Decimal::ZERO),
+                    cum_qty:   x.cum_qty
+                }))).collect(),
+        Table(Position{ ref data, .. }) =>
+            data.iter().map(|x|
+                UpdatePosition(model::Position {
+                    current_qty:       x.current_qty,
+                    avg_entry_price:   x.avg_entry_price,
+                    liquidation_price: x.liquidation_price,
+                    unrealised_pnl:    x.unrealised_pnl,
+                    realised_pnl:      x.realised_pnl,
+                    leverage:          x.leverage,
+                    mark_price:        x.mark_price
                 })).collect(),
         e @ Table { .. } => {
             log::info!("ignoring other table: {:?}", e);
@@ -70,6 +272,39 @@ fn ws_resp_2_orchestrator_event(resp: &Response) -> Vec<OrchestratorEvent> {
 }
 
 
+#[derive(Error, Debug)]
+pub enum WsError {
+    #[error("ws connection error: {0:?}")]
+    ConnectionError(Box<tungstenite::Error>),
+    #[error("json serialization error: {0:?}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("no pong received for {0:?}")]
+    HeartbeatTimeout(time::Duration),
+    #[error("orchestrator channel closed")]
+    ChannelClosed,
+    #[error("stopped")]
+    Stopped,
+}
+
+impl From<io::Error> for WsError {
+    fn from(err: io::Error) -> Self {
+        WsError::ConnectionError(Box::new(tungstenite::Error::Io(err)))
+    }
+}
+
+impl From<tungstenite::Error> for WsError {
+    fn from(err: tungstenite::Error) -> Self {
+        WsError::ConnectionError(Box::new(err))
+    }
+}
+
+impl From<mpsc::SendError<OrchestratorEvent>> for WsError {
+    fn from(_: mpsc::SendError<OrchestratorEvent>) -> Self {
+        WsError::ChannelClosed
+    }
+}
+
+
 /// Example of internal tests, allows for testing non-public fns.
 #[cfg(test)]
 mod tests {
@@ -90,9 +325,15 @@ mod tests {
                     ord_status: OrderStatus::New,
                     ord_type: None,
                     order_qty: None,
-                    price: None
+                    price: None,
+                    stop_px: None,
+                    cum_qty: None,
+                    leaves_qty: None,
+                    avg_px: None,
+                    time_in_force: None,
+                    exec_inst: None
                 })})),
-                vec!(UpdateOrder(ExchangeOrder { cl_ord_id: "12345".to_string(), ord_status: OrderStatus::New, ord_type: None, price: None, qty: None, side: None }))
+                vec!(UpdateOrder(ExchangeOrder { cl_ord_id: "12345".to_string(), ord_status: OrderStatus::New, ord_type: None, price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }))
         );
     }
 }
@@ -1,10 +1,12 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
 use chrono::prelude::*;
-use serde::{Deserialize, Serialize};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use uuid::Uuid;
 
-use super::model::{OrderStatus, OrderType, Side};
+use super::model::{OrderStatus, OrderType, Side, TickDirection, TimeInForce};
 
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -12,8 +14,14 @@ use super::model::{OrderStatus, OrderType, Side};
 #[serde(tag = "op", content = "args")]
 pub enum Request{
     Subscribe(Vec<String>),
+    Unsubscribe(Vec<String>),
     #[serde(rename = "authKeyExpires")]
-    Authenticate(String, i64, String)
+    Authenticate(String, #[serde(deserialize_with = "de_number")] i64, String)
+}
+
+/// Integers nested in untagged/internally tagged enums arrive buffered as arbitrary precision numbers.
+fn de_number<'de, D: Deserializer<'de>, T: FromStr>(d: D) -> Result<T, D::Error> where T::Err: Display {
+    serde_json::Number::deserialize(d)?.to_string().parse().map_err(de::Error::custom)
 }
 
 #[derive(Serialize, Deserialize, Debug, Display, PartialEq)]
@@ -26,14 +34,6 @@ pub enum TableAction {
     Delete,
 }
 
-#[derive(Serialize, Deserialize, Debug, Display, PartialEq)]
-pub enum TickDirection {
-    ZeroPlusTick,
-    PlusTick,
-    ZeroMinusTick,
-    MinusTick,
-}
-
 #[derive(Deserialize, Debug, Serialize, PartialEq)]
 #[serde(tag = "table")]
 #[serde(rename_all = "camelCase")]
@@ -45,11 +45,11 @@ pub enum Table {
     },
     OrderBookL2 {
         action: TableAction,
-        data: Vec<OrderBookRow>
+        data: Vec<OrderBookL2Row>
     },
     OrderBookL2_25 {
         action: TableAction,
-        data: Vec<OrderBookRow>
+        data: Vec<OrderBookL2Row>
     },
     OrderBook10 {
         action: TableAction,
@@ -62,6 +62,14 @@ pub enum Table {
     Funding {
         action: TableAction,
         data: Vec<FundingRow>
+    },
+    Position {
+        action: TableAction,
+        data: Vec<PositionRow>
+    },
+    Execution {
+        action: TableAction,
+        data: Vec<ExecutionRow>
     }
 }
 
@@ -78,6 +86,7 @@ pub enum Response {
         success: bool,
     },
     Error {
+        #[serde(deserialize_with = "de_number")]
         status: u16,
         error: String,
         request: Request,
@@ -93,14 +102,14 @@ pub struct TradeRow {
     pub timestamp: DateTime<Utc>,
     pub symbol: String,
     pub side: Side,
-    pub size: u64,
-    pub price: f64,
+    pub size: Decimal,
+    pub price: Decimal,
     pub tick_direction: TickDirection,
     #[serde(rename = "trdMatchID")]
     pub trade_match_id: Uuid,
-    pub gross_value: Option<u64>,
-    pub home_notional: Option<f64>,
-    pub foreign_notional: Option<f64>,
+    pub gross_value: Option<Decimal>,
+    pub home_notional: Option<Decimal>,
+    pub foreign_notional: Option<Decimal>,
 }
 
 #[derive(Deserialize, Debug, Serialize, PartialEq)]
@@ -114,8 +123,34 @@ pub struct OrderRow {
     pub side: Option<Side>,
     pub ord_status: OrderStatus,
     pub ord_type: Option<OrderType>,
-    pub order_qty: Option<f64>,
-    pub price: Option<f64>,
+    pub order_qty: Option<Decimal>,
+    pub price: Option<Decimal>,
+    pub stop_px: Option<Decimal>,
+    pub cum_qty: Option<Decimal>,
+    pub leaves_qty: Option<Decimal>,
+    pub avg_px: Option<Decimal>,
+    pub time_in_force: Option<TimeInForce>,
+    pub exec_inst: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
+//#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionRow {
+    pub timestamp: Option<DateTime<Utc>>,
+    pub symbol: String,
+    #[serde(rename = "execID")]
+    pub exec_id: String,
+    #[serde(rename = "clOrdID")]
+    pub cl_ord_id: String,
+    pub side: Option<Side>,
+    pub exec_type: String,
+    pub ord_status: Option<OrderStatus>,
+    pub last_qty: Option<Decimal>,
+    pub last_px: Option<Decimal>,
+    pub cum_qty: Option<Decimal>,
+    pub leaves_qty: Option<Decimal>,
+    pub avg_px: Option<Decimal>,
 }
 
 #[derive(Deserialize, Debug, Serialize, PartialEq)]
@@ -125,8 +160,22 @@ pub struct FundingRow {
     pub timestamp: DateTime<Utc>,
     pub symbol: String,
     pub funding_interval: DateTime<Utc>,
-    pub funding_rate: f64,
-    pub funding_rate_daily: f64,
+    pub funding_rate: Decimal,
+    pub funding_rate_daily: Decimal,
+}
+
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
+//#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionRow {
+    pub symbol: String,
+    pub current_qty: Option<Decimal>,
+    pub avg_entry_price: Option<Decimal>,
+    pub liquidation_price: Option<Decimal>,
+    pub unrealised_pnl: Option<Decimal>,
+    pub realised_pnl: Option<Decimal>,
+    pub leverage: Option<Decimal>,
+    pub mark_price: Option<Decimal>,
 }
 
 #[derive(Deserialize, Debug, Serialize, PartialEq)]
@@ -135,15 +184,28 @@ pub struct FundingRow {
 pub struct OrderBookRow {
     pub timestamp: DateTime<Utc>,
     pub symbol: String,
-    pub asks: Vec<[f64; 2]>,
-    pub bids: Vec<[f64; 2]>
+    pub asks: Vec<[Decimal; 2]>,
+    pub bids: Vec<[Decimal; 2]>
 }
 
 impl OrderBookRow {
-  pub fn first_bid(&self) -> f64 {
-      self.bids.iter().map(|x| x[0]).fold(f64::NAN, f64::max)
+  pub fn first_bid(&self) -> Option<Decimal> {
+      self.bids.iter().map(|x| x[0]).max()
   }
-  pub fn first_ask(&self) -> f64 {
-      self.asks.iter().map(|x| x[0]).fold(f64::NAN, f64::max)
+  pub fn first_ask(&self) -> Option<Decimal> {
+      self.asks.iter().map(|x| x[0]).min()
   }
 }
+
+/// Level of orderBookL2/orderBookL2_25, only partial/insert carry the price.
+#[derive(Deserialize, Debug, Serialize, PartialEq)]
+//#[serde(deny_unknown_fields)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBookL2Row {
+    pub symbol: String,
+    #[serde(deserialize_with = "de_number")]
+    pub id: u64,
+    pub side: Side,
+    pub size: Option<Decimal>,
+    pub price: Option<Decimal>,
+}
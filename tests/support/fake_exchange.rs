@@ -0,0 +1,87 @@
+//! In-memory exchange for tests.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use rust_decimal::Decimal;
+
+use bitmex_warrior::exchange::{Exchange, ExchangeError, Subscription};
+use bitmex_warrior::model::{ExchangeOrder, Instrument, OrchestratorEvent, OrchestratorEvent::*, OrderStatus};
+use bitmex_warrior::rest::RestError;
+
+/// In-memory exchange for tests, recording cmds and acknowledging them as New/Canceled, and pushing scripted market data.
+#[derive(Debug, Default)]
+pub struct FakeExchange {
+    pub issued:      Vec<ExchangeOrder>,
+    pub canceled:    Vec<String>,
+    pub canceled_all_count: usize,
+    pub is_cancel_all_failing: bool,
+    pub is_issue_failing: bool,
+    pub cancel_all_after: Vec<Duration>,
+    pub amended:     Vec<ExchangeOrder>,
+    pub market_data: Vec<OrchestratorEvent>,
+    pub instrument:  Option<Instrument>,
+}
+
+impl FakeExchange {
+    pub fn new(market_data: Vec<OrchestratorEvent>) -> Self {
+        FakeExchange { market_data, .. FakeExchange::default() }
+    }
+}
+
+#[async_trait]
+impl Exchange for FakeExchange {
+    async fn issue_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.issued.push(order.clone());
+        if self.is_issue_failing {
+            return Err(failure())
+        }
+        Ok(vec!(UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, cum_qty: Some(Decimal::ZERO), leaves_qty: order.qty, .. order.clone() })))
+    }
+
+    async fn cancel_orders(&mut self, cl_ord_ids: &[&str]) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.canceled.extend(cl_ord_ids.iter().map(|x| x.to_string()));
+        Ok(cl_ord_ids.iter().map(|x| UpdateOrder(ExchangeOrder { cl_ord_id: x.to_string(), ord_status: OrderStatus::Canceled, ord_type: None, price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None })).collect())
+    }
+
+    async fn cancel_all_orders(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.canceled_all_count += 1;
+        if self.is_cancel_all_failing {
+            return Err(failure())
+        }
+        Ok(Vec::new())
+    }
+
+    async fn amend_order(&mut self, order: &ExchangeOrder) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.amended.push(order.clone());
+        Ok(vec!(UpdateOrder(order.clone())))
+    }
+
+    async fn cancel_all_after(&mut self, timeout: Duration) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        self.cancel_all_after.push(timeout);
+        let cancel_at = Some(Utc::now() + chrono::Duration::from_std(timeout).unwrap()).filter(|_| !timeout.is_zero());
+        Ok(vec!(UpdateDeadMansSwitch(cancel_at)))
+    }
+
+    async fn fetch_instrument(&mut self) -> Result<Vec<OrchestratorEvent>, ExchangeError> {
+        Ok(self.instrument.iter().map(|x| NewInstrument(x.clone())).collect())
+    }
+
+    fn subscribe(&self, tx: mpsc::Sender<OrchestratorEvent>) -> Subscription {
+        let market_data = self.market_data.clone();
+        Subscription::from(thread::spawn(move || {
+            for event in market_data {
+                if tx.send(event).is_err() {
+                    return
+                }
+            }
+        }))
+    }
+}
+
+fn failure() -> ExchangeError {
+    ExchangeError::RestError(RestError::ParseError(serde_json::from_str::<serde_json::Value>("").unwrap_err()))
+}
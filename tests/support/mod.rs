@@ -0,0 +1,221 @@
+//! Local BitMEX mock server, speaking the REST order endpoints and the /realtime WS, for offline integration tests.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde_json::json;
+use tungstenite::Message;
+
+use bitmex_warrior::model::{OrderStatus, Side};
+use bitmex_warrior::rest_model::Order;
+use bitmex_warrior::sign::sign;
+use bitmex_warrior::ws_model::Request;
+
+pub const API_KEY: &str = "mock-api-key";
+pub const API_SECRET: &str = "mock-api-secret";
+const POSITION_QTY: Decimal = Decimal::ONE_HUNDRED;
+
+/// Received http request, with whether its api-signature verified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+    pub is_authenticated: bool,
+}
+
+/// Mock server, on ephemeral localhost ports. Orders are acknowledged as New/Canceled, and once all subscriptions of a
+/// WS connection are acked, the scripted table pushes are sent.
+pub struct MockBitmex {
+    pub http_url: String,
+    pub wss_url: String,
+    pub requests: Arc<Mutex<Vec<MockRequest>>>,
+    pub subscriptions: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockBitmex {
+    pub fn start(ws_pushes: Vec<String>) -> MockBitmex {
+        let http_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let ws_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mock = MockBitmex {
+            http_url: format!("http://{}", http_listener.local_addr().unwrap()),
+            wss_url: format!("ws://{}/realtime", ws_listener.local_addr().unwrap()),
+            requests: Arc::new(Mutex::new(Vec::new())),
+            subscriptions: Arc::new(Mutex::new(Vec::new())),
+        };
+
+        let requests = mock.requests.clone();
+        let orders: Arc<Mutex<HashMap<String, Order>>> = Arc::new(Mutex::new(HashMap::new()));
+        thread::spawn(move || {
+            for stream in http_listener.incoming().flatten() {
+                let (requests, orders) = (requests.clone(), orders.clone());
+                thread::spawn(move || handle_http(stream, &requests, &orders));
+            }
+        });
+
+        let subscriptions = mock.subscriptions.clone();
+        thread::spawn(move || {
+            for stream in ws_listener.incoming().flatten() {
+                let (subscriptions, ws_pushes) = (subscriptions.clone(), ws_pushes.clone());
+                thread::spawn(move || handle_ws(stream, &subscriptions, &ws_pushes));
+            }
+        });
+        mock
+    }
+}
+
+fn is_valid_signature(verb: &str, path: &str, expires: &str, body: &str, api_key: &str, signature: &str) -> bool {
+    let is_expired = expires.parse::<i64>().map_or(true, |x| x < Utc::now().timestamp());
+    api_key == API_KEY && !is_expired && signature == sign(&format!("{}{}{}{}", verb, path, expires, body), API_SECRET)
+}
+
+fn handle_http(stream: TcpStream, requests: &Mutex<Vec<MockRequest>>, orders: &Mutex<HashMap<String, Order>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut request_line = request_line.split_whitespace();
+    let (method, path) = (request_line.next().unwrap_or_default().to_string(), request_line.next().unwrap_or_default().to_string());
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        match header.trim_end().split_once(':') {
+            Some((k, v)) => headers.insert(k.trim().to_lowercase(), v.trim().to_string()),
+            None => break
+        };
+    }
+    let mut body = vec![0; headers.get("content-length").map_or(0, |x| x.parse().unwrap())];
+    reader.read_exact(&mut body).unwrap();
+    let body = String::from_utf8(body).unwrap();
+
+    let header = |k: &str| headers.get(k).map_or("", String::as_str);
+    let is_authenticated = is_valid_signature(&method, &path, header("api-expires"), &body, header("api-key"), header("api-signature"));
+    requests.lock().unwrap().push(MockRequest { method: method.clone(), path: path.clone(), body: body.clone(), is_authenticated });
+
+    let params = body.split('&')
+        .filter_map(|x| x.split_once('='))
+        .map(|(k, v)| (k.to_string(), urlencoding::decode(v).unwrap()))
+        .collect::<HashMap<String, String>>();
+    let (status, resp_body) = match (is_authenticated, method.as_str(), path.as_str()) {
+        (_, "GET", "/api/v1/instrument?symbol=XBTUSD") =>  // public
+            (200, json!([{"symbol": "XBTUSD", "tickSize": 0.5, "lotSize": 100, "multiplier": -100000000, "isInverse": true, "maxOrderQty": 10000000, "markPrice": 50000.25}])),
+        (_, "GET", path) if path.starts_with("/api/v1/instrument") => (200, json!([])),
+        (false, _, _) => (401, json!({"error": {"message": "Signature not valid.", "name": "HTTPError"}})),
+        (true, "POST", "/api/v1/order") if params.get("price").is_some_and(|x| x.parse::<Decimal>().unwrap() <= Decimal::ZERO) =>
+            (400, json!({"error": {"message": "Invalid price", "name": "ValidationError"}})),
+        (true, "POST", "/api/v1/order") => {
+            // execInst=Close orders sans orderQty close the mock's long position, at once
+            let is_close = params.get("execInst").is_some_and(|x| x.contains("Close"));
+            let qty = params.get("orderQty").map_or(POSITION_QTY, |x| x.parse().unwrap());
+            let order = Order {
+                cl_ord_id: params["clOrdID"].clone(),
+                symbol: params["symbol"].clone(),
+                side: params.get("side").map_or(Side::Sell, |x| serde_json::from_value(json!(x)).unwrap()),
+                order_qty: qty,
+                ord_status: if is_close { OrderStatus::Filled } else { OrderStatus::New },
+                ord_type: serde_json::from_value(json!(params["ordType"])).unwrap(),
+                price: params.get("price").map(|x| x.parse().unwrap()),
+                stop_px: params.get("stopPx").map(|x| x.parse().unwrap()),
+                cum_qty: Some(if is_close { qty } else { Decimal::ZERO }),
+                leaves_qty: Some(if is_close { Decimal::ZERO } else { qty }),
+                avg_px: None,
+                time_in_force: serde_json::from_value(json!(params["timeInForce"])).unwrap(),
+                exec_inst: params.get("execInst").cloned(),
+            };
+            let resp_body = serde_json::to_value(&order).unwrap();
+            orders.lock().unwrap().insert(order.cl_ord_id.clone(), order);
+            (200, resp_body)
+        }
+        (true, "PUT", "/api/v1/order") => match orders.lock().unwrap().get_mut(&params["origClOrdID"]) {
+            Some(order) => {
+                order.price = params.get("price").map(|x| x.parse().unwrap()).or(order.price);
+                (200, serde_json::to_value(&order).unwrap())
+            }
+            None => (400, json!({"error": {"message": "Invalid origClOrdID", "name": "HTTPError"}}))
+        }
+        (true, "POST", "/api/v1/order/cancelAllAfter") => match params["timeout"].parse::<i64>().unwrap() {
+            0 => (200, json!({})),  // disarmed
+            timeout => (200, json!({"now": Utc::now(), "cancelTime": Utc::now() + chrono::Duration::milliseconds(timeout)}))
+        }
+        (true, "DELETE", "/api/v1/order/all") => {
+            let mut orders = orders.lock().unwrap();
+            let mut canceled = Vec::new();
+            for order in orders.values_mut().filter(|x| x.symbol == params["symbol"] && x.ord_status == OrderStatus::New) {
+                order.ord_status = OrderStatus::Canceled;
+                canceled.push(serde_json::to_value(&*order).unwrap());
+            }
+            (200, json!(canceled))
+        }
+        (true, "DELETE", "/api/v1/order") => {
+            let cl_ord_ids = serde_json::from_str::<Vec<String>>(&params["clOrdID"]).unwrap_or_else(|_| vec![params["clOrdID"].clone()]);
+            let mut orders = orders.lock().unwrap();
+            let mut canceled = Vec::new();
+            for order in orders.values_mut().filter(|x| cl_ord_ids.contains(&x.cl_ord_id)) {
+                order.ord_status = OrderStatus::Canceled;
+                canceled.push(serde_json::to_value(&*order).unwrap());
+            }
+            (200, json!(canceled))
+        }
+        _ => (404, json!({"error": {"message": "Not Found", "name": "HTTPError"}}))
+    };
+
+    let resp_body = resp_body.to_string();
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} MOCK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", status, resp_body.len(), resp_body).unwrap();
+}
+
+fn handle_ws(stream: TcpStream, subscriptions: &Mutex<Vec<String>>, ws_pushes: &[String]) {
+    let mut ws_socket = tungstenite::accept(stream.try_clone().unwrap()).unwrap();
+    let send = |ws_socket: &mut tungstenite::WebSocket<TcpStream>, msg: String| ws_socket.write_message(Message::text(msg)).is_ok();
+    if !send(&mut ws_socket, json!({"info": "Welcome to the BitMEX Realtime API.", "version": "mock", "timestamp": Utc::now()}).to_string()) {
+        return
+    }
+    stream.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut is_pushed = false;
+    loop {
+        let payload = match ws_socket.read_message() {
+            Ok(Message::Text(payload)) => payload,
+            Ok(Message::Close(_)) => return,
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(ref err)) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => continue,
+            Err(_) => return
+        };
+        if payload == "ping" {
+            if !send(&mut ws_socket, "pong".to_string()) {
+                return
+            }
+            continue
+        }
+        let request: serde_json::Value = serde_json::from_str(&payload).unwrap_or_default();
+        let is_sent = match serde_json::from_str::<Request>(&payload) {
+            Ok(Request::Authenticate(api_key, expires, signature)) if is_valid_signature("GET", "/realtime", &expires.to_string(), "", &api_key, &signature) =>
+                send(&mut ws_socket, json!({"success": true, "request": request}).to_string()),
+            Ok(Request::Authenticate(..)) => {
+                send(&mut ws_socket, json!({"status": 401, "error": "Signature not valid.", "request": request}).to_string());
+                ws_socket.close(None).ok();
+                false
+            }
+            Ok(Request::Subscribe(topics)) => {
+                let is_acked = topics.iter().all(|x| send(&mut ws_socket, json!({"success": true, "subscribe": x, "request": request}).to_string()));
+                subscriptions.lock().unwrap().extend(topics);
+                let is_first_subscribe = !is_pushed;
+                is_pushed = true;
+                is_acked && (!is_first_subscribe || ws_pushes.iter().all(|x| send(&mut ws_socket, x.clone())))
+            }
+            Ok(Request::Unsubscribe(topics)) =>
+                topics.iter().all(|x| send(&mut ws_socket, json!({"success": true, "unsubscribe": x, "request": request}).to_string())),
+            Err(_) =>
+                send(&mut ws_socket, json!({"status": 400, "error": format!("Unknown or malformed request: {}", payload)}).to_string()),
+        };
+        if !is_sent {
+            return
+        }
+    }
+}
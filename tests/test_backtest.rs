@@ -0,0 +1,86 @@
+use std::io;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use bitmex_warrior::backtest::{self, Chase, Fees};
+use bitmex_warrior::model::*;
+use bitmex_warrior::recorder::RecordedFrame;
+use bitmex_warrior::replay::Replay;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+const FEES: Fees = Fees { maker_rate: dec!(-0.00025), taker_rate: dec!(0.00075) };
+
+fn at(secs: i64) -> DateTime<Utc> {
+    Utc.timestamp(1_600_000_000 + secs, 0)
+}
+
+fn book(secs: i64, bid: Decimal, ask: Decimal) -> io::Result<RecordedFrame> {
+    Ok(RecordedFrame { received_at: at(secs), frame: format!(r#"{{"table":"orderBook10","action":"update","data":[{{"symbol":"XBTUSD","bids":[[{},100]],"asks":[[{},100]],"timestamp":"2020-01-01T00:00:00Z"}}]}}"#, bid, ask) })
+}
+
+fn trade(secs: i64, side: &str, size: u64, price: Decimal) -> io::Result<RecordedFrame> {
+    Ok(RecordedFrame { received_at: at(secs), frame: format!(r#"{{"table":"trade","action":"insert","data":[{{"timestamp":"2020-01-01T00:00:00Z","symbol":"XBTUSD","side":"{}","size":{},"price":{},"tickDirection":"ZeroPlusTick","trdMatchID":"00000000-0000-0000-0000-000000000001"}}]}}"#, side, size, price) })
+}
+
+/// Bid lifts from 100 to 101, trades through 101, then the market drops to 99.5 and recovers to 101.
+fn frames() -> Vec<io::Result<RecordedFrame>> {
+    vec![book(0, dec!(100), dec!(100.5)), book(10, dec!(101), dec!(101.5)), trade(20, "Sell", 500, dec!(100.5)), book(30, dec!(99.5), dec!(100)), book(40, dec!(101), dec!(101.5))]
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_backtest__chase() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let report = backtest::run(Replay::new(frames().into_iter(), vec![]), state, Some(Box::new(Chase::new(Side::Buy))), FEES).unwrap();
+
+    // joined at 100, chased to 101, filled passively when traded through
+    assert_eq!(report.fills.len(), 1);
+    let fill = &report.fills[0];
+    assert_eq!((fill.fill.side, fill.fill.qty, fill.fill.price, fill.fill.is_maker), (Side::Buy, dec!(10), dec!(101), true));
+    assert_eq!(fill.slippage, dec!(1));
+    assert_eq!(report.fees, dec!(10) * dec!(101) * dec!(-0.00025));
+    assert_eq!(report.position_qty, dec!(10));
+    assert_eq!(report.unrealised_pnl, dec!(10) * (dec!(101.25) - dec!(101)));
+    assert_eq!(report.max_drawdown, dec!(10) * (dec!(101.25) - dec!(99.75)));  // mid peak post fill to trough
+    assert_eq!(report.net_pnl, report.unrealised_pnl - report.fees);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_backtest__chase_inverse_off_the_touch() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.instrument = Some(Instrument { symbol: "XBTUSD".to_string(), tick_size: dec!(0.5), lot_size: dec!(1), multiplier: dec!(-100000000), is_inverse: true, max_order_qty: None });
+    state.price_input = Some(PriceInput::Ticks(-2));
+    let report = backtest::run(Replay::new(frames().into_iter(), vec![]), state, Some(Box::new(Chase::new(Side::Buy))), FEES).unwrap();
+
+    // joined 2 ticks under the bid, slippage still measured from the bid when sent
+    assert_eq!(report.fills.iter().map(|x| (x.fill.price, x.slippage)).collect::<Vec<_>>(), vec![(dec!(101), dec!(1))]);
+    // in XBT, ie. qty / price
+    assert_eq!(report.fees, dec!(10) / dec!(101) * dec!(-0.00025));
+    assert_eq!(report.unrealised_pnl.round_dp(12), (dec!(10) / dec!(101) - dec!(10) / dec!(101.25)).round_dp(12));
+    assert_eq!(report.net_pnl, report.unrealised_pnl - report.fees);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_backtest__script() {
+    let script = backtest::parse_script("
+        # round trip, taking liquidity
+        2020-09-13T12:26:45Z buy-ask
+        2020-09-13T12:27:15Z sell-bid
+    ").unwrap();
+    assert_eq!(script.iter().map(|x| x.0).collect::<Vec<_>>(), vec![at(5), at(35)]);
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let report = backtest::run(Replay::new(frames().into_iter(), script), state, None, FEES).unwrap();
+
+    assert_eq!(report.fills.iter().map(|x| (x.fill.side, x.fill.price, x.fill.is_maker)).collect::<Vec<_>>(), vec![(Side::Buy, dec!(100.5), false), (Side::Sell, dec!(99.5), false)]);
+    assert_eq!(report.realised_pnl, dec!(-10));
+    assert_eq!(report.position_qty, dec!(0));
+    assert_eq!(report.avg_slippage, dec!(0));
+    assert_eq!(report.fees, (dec!(100.5) + dec!(99.5)) * dec!(10) * dec!(0.00075));
+
+    assert_eq!(backtest::parse_script("2020-09-13T12:26:45Z buy-at-will"), Err("line 1: unknown action buy-at-will".to_string()));
+    assert_eq!(OrchestratorEvent::parse_action("cancel-3"), Some(OrchestratorEvent::CancelAt(3)));
+}
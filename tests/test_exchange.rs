@@ -0,0 +1,165 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use bitmex_warrior::exchange::{Exchange, PaperExchange};
+use bitmex_warrior::model::*;
+use bitmex_warrior::orchestrator;
+use rust_decimal_macros::dec;
+
+#[path = "support/fake_exchange.rs"]
+mod fake_exchange;
+use fake_exchange::FakeExchange;
+
+fn market_data() -> Vec<OrchestratorEvent> {
+    vec![
+        OrchestratorEvent::NewConnectionState(ConnectionState::Connected),
+        OrchestratorEvent::NewDepth(vec![Level { price: dec!(100), size: dec!(50) }], vec![Level { price: dec!(101), size: dec!(30) }]),
+        OrchestratorEvent::NewBid(dec!(100)),
+        OrchestratorEvent::NewAsk(dec!(101)),
+    ]
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__fake_orchestrator_loop() {
+    let mut exchange = FakeExchange::new([market_data(), vec![OrchestratorEvent::Buy(PriceType::Bid), OrchestratorEvent::Sell(PriceType::Ask)]].concat());
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx.clone()).join().unwrap();
+
+    // cancel all once both orders are acknowledged, exit once the cancels are
+    let tx2 = tx.clone();
+    let mut has_canceled = false;
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(orchestrator::run(&mut exchange, state, &rx, &tx, |state| {
+        if !has_canceled && state.live_orders().filter(|x| x.ord_status == OrderStatus::New).count() == 2 {
+            has_canceled = true;
+            tx2.send(OrchestratorEvent::CancelAll).unwrap();
+        } else if has_canceled && state.orders.is_empty() {
+            tx2.send(OrchestratorEvent::Exit).unwrap();
+        }
+    }));
+
+    assert_eq!(exchange.issued.iter().map(|x| (x.side, x.price)).collect::<Vec<_>>(), vec![(Some(Side::Buy), Some(dec!(100))), (Some(Side::Sell), Some(dec!(101)))]);
+    assert_eq!(exchange.canceled, exchange.issued.iter().map(|x| x.cl_ord_id.clone()).collect::<Vec<_>>());
+    assert!(exchange.amended.is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__dead_mans_switch() {
+    let mut exchange = FakeExchange::new([market_data(), vec![OrchestratorEvent::RefreshDeadMansSwitch]].concat());
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.dead_mans_switch = Some(DeadMansSwitch { timeout: Duration::from_secs(60), cancel_at: None });
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx.clone()).join().unwrap();
+
+    // exit once armed, disarming it on the way out
+    let tx2 = tx.clone();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(orchestrator::run(&mut exchange, state, &rx, &tx, |state| {
+        if state.dead_mans_switch.as_ref().is_some_and(|x| x.cancel_at.is_some()) {
+            tx2.send(OrchestratorEvent::Exit).unwrap();
+        }
+    }));
+
+    assert_eq!(exchange.cancel_all_after, vec![Duration::from_secs(60), Duration::ZERO]);
+    assert_eq!(state.status, "Dead man's switch armed, 60s timeout");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__batch_carries_on_past_failures() {
+    let mut exchange = FakeExchange { is_cancel_all_failing: true, ..FakeExchange::default() };
+    let order = ExchangeOrder { cl_ord_id: "flatten".to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(OrderType::Market), price: None, qty: Some(dec!(10)), side: Some(Side::Sell), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let events = rt.block_on(exchange.execute(&ExchangeCmd::Batch(vec!(ExchangeCmd::CancelAllOrders, ExchangeCmd::IssueOrder(order.clone()))))).unwrap();
+
+    assert_eq!(exchange.issued, vec!(order));
+    assert!(matches!(events.as_slice(), [OrchestratorEvent::NewStatus(status), OrchestratorEvent::UpdateOrder(o)]
+        if status.starts_with("Failed to execute CancelAllOrders: rest error: json parse error") && o.ord_status == OrderStatus::New), "{:?}", events);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__paper_drops_private_events() {
+    let live = vec![
+        OrchestratorEvent::UpdatePosition(Position { current_qty: Some(dec!(500)), ..Position::default() }),
+        OrchestratorEvent::SyncLiveOrders(vec![]),
+        OrchestratorEvent::NewFill(Fill { cl_ord_id: "live".to_string(), exec_id: "1".to_string(), side: Side::Buy, last_qty: dec!(10), last_px: dec!(100), cum_qty: None }),
+    ];
+    let exchange = PaperExchange::new(FakeExchange::new([market_data(), live].concat()));
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx).join().unwrap();
+    assert_eq!(rx.iter().collect::<Vec<_>>(), market_data());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__failed_issue_rejected() {
+    let mut exchange = FakeExchange { is_issue_failing: true, ..FakeExchange::new([market_data(), vec![OrchestratorEvent::Buy(PriceType::Bid)]].concat()) };
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx.clone()).join().unwrap();
+
+    let tx2 = tx.clone();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(orchestrator::run(&mut exchange, state, &rx, &tx, |state| {
+        if state.status.starts_with("Failed to execute") {
+            tx2.send(OrchestratorEvent::Exit).unwrap();
+        }
+    }));
+
+    // dropped rather than left NotYetIssued
+    assert_eq!(exchange.issued.len(), 1);
+    assert!(state.orders.is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__paper_orchestrator_loop() {
+    let mut exchange = PaperExchange::new(FakeExchange::new([market_data(), vec![OrchestratorEvent::Buy(PriceType::Ask)]].concat()));
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx.clone()).join().unwrap();
+
+    let tx2 = tx.clone();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(orchestrator::run(&mut exchange, state, &rx, &tx, |state| {
+        if state.position.as_ref().and_then(|x| x.current_qty) == Some(dec!(10)) {
+            tx2.send(OrchestratorEvent::Exit).unwrap();
+        }
+    }));
+
+    // market data is fed to the sim, never reaching the underlying exchange's order entry
+    assert!(exchange.market_data.issued.is_empty());
+    assert_eq!(state.position.as_ref().and_then(|x| x.avg_entry_price), Some(dec!(101)));
+    assert!(state.orders.is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_exchange__paper_flatten_all() {
+    let mut exchange = PaperExchange::new(FakeExchange::new([market_data(), vec![OrchestratorEvent::Buy(PriceType::Ask), OrchestratorEvent::Sell(PriceType::Ask)]].concat()));
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (tx, rx) = mpsc::channel();
+    exchange.subscribe(tx.clone()).join().unwrap();
+
+    // flatten once long, with a resting sell, exit once done
+    let tx2 = tx.clone();
+    let mut has_flattened = false;
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(orchestrator::run(&mut exchange, state, &rx, &tx, |state| {
+        if !has_flattened && state.position.as_ref().and_then(|x| x.current_qty) == Some(dec!(10)) && state.live_orders().count() == 1 {
+            has_flattened = true;
+            tx2.send(OrchestratorEvent::FlattenAll).unwrap();
+        } else if state.status.starts_with("Flatten all done") {
+            tx2.send(OrchestratorEvent::Exit).unwrap();
+        }
+    }));
+
+    assert_eq!(state.status, "Flatten all done: canceled 1 orders, close order Filled, filled 10/10 @ avg 100");
+    assert_eq!(state.position.as_ref().and_then(|x| x.current_qty), Some(dec!(0)));
+    assert!(state.orders.is_empty());
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+use termion::event::Key;
+use uuid::Uuid;
+
+use bitmex_warrior::keys::{KeyBindingError, KeyBindings, KeyMapper};
+use bitmex_warrior::model::*;
+use rust_decimal_macros::dec;
+
+fn actions(bindings: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+    bindings.iter().map(|(action, keys)| (action.to_string(), keys.iter().map(|x| x.to_string()).collect())).collect()
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__defaults() {
+    let mut mapper = KeyMapper::new(KeyBindings::from_config(None).unwrap());
+    assert_eq!(vec!(OrchestratorEvent::Buy(PriceType::Bid)), mapper.on_key(Key::Char('z')));
+    assert_eq!(vec!(OrchestratorEvent::UpQty), mapper.on_key(Key::Char('=')));
+    assert_eq!(vec!(OrchestratorEvent::UpLastPrice), mapper.on_key(Key::Up));
+    assert_eq!(vec!(OrchestratorEvent::CancelAll), mapper.on_key(Key::Char('C')));
+    assert_eq!(vec!(OrchestratorEvent::CancelAt(7)), mapper.on_key(Key::Char('7')));
+    assert_eq!(vec!(OrchestratorEvent::Exit), mapper.on_key(Key::Ctrl('c')));
+    assert!(mapper.on_key(Key::Char('q')).is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__modifiers_and_chords() {
+    let bindings = KeyBindings::new(&actions(&[
+        ("buy-bid", &["ctrl-b", "g b"]),
+        ("sell-ask", &["alt-s", "g s"]),
+        ("cancel-all", &["f12"]),
+        ("exit", &["esc"]),
+    ])).unwrap();
+    let mut mapper = KeyMapper::new(bindings);
+    assert_eq!(vec!(OrchestratorEvent::Buy(PriceType::Bid)), mapper.on_key(Key::Ctrl('b')));
+    assert_eq!(vec!(OrchestratorEvent::Sell(PriceType::Ask)), mapper.on_key(Key::Alt('s')));
+    assert_eq!(vec!(OrchestratorEvent::CancelAll), mapper.on_key(Key::F(12)));
+    assert!(mapper.on_key(Key::Char('g')).is_empty());
+    assert_eq!(vec!(OrchestratorEvent::Sell(PriceType::Ask)), mapper.on_key(Key::Char('s')));
+    // abandoned chord, the breaking key is resolved afresh
+    assert!(mapper.on_key(Key::Char('g')).is_empty());
+    assert_eq!(vec!(OrchestratorEvent::Exit), mapper.on_key(Key::Esc));
+    assert!(mapper.on_key(Key::Char('g')).is_empty());
+    assert_eq!(vec!(OrchestratorEvent::Buy(PriceType::Bid)), mapper.on_key(Key::Char('b')));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__input_line() {
+    let mut mapper = KeyMapper::new(KeyBindings::from_config(None).unwrap());
+    assert_eq!(vec!(OrchestratorEvent::EditInput(Some("".to_string()))), mapper.on_key(Key::Char(':')));
+    // bound keys are typed whilst the input line is open
+    for c in "q 25".chars() {
+        mapper.on_key(Key::Char(c));
+    }
+    assert_eq!(vec!(OrchestratorEvent::EditInput(Some("q 2".to_string()))), mapper.on_key(Key::Backspace));
+    assert_eq!(vec!(OrchestratorEvent::EditInput(Some("q 20".to_string()))), mapper.on_key(Key::Char('0')));
+    assert_eq!(vec!(OrchestratorEvent::EditInput(None), OrchestratorEvent::SetQty(dec!(20))), mapper.on_key(Key::Char('\n')));
+    assert_eq!(vec!(OrchestratorEvent::Buy(PriceType::Bid)), mapper.on_key(Key::Char('z')));
+
+    mapper.on_key(Key::Char(':'));
+    for c in "p -0.1%".chars() {
+        mapper.on_key(Key::Char(c));
+    }
+    assert_eq!(vec!(OrchestratorEvent::EditInput(None), OrchestratorEvent::SetPrice(Some(PriceInput::Percent(dec!(-0.1))))), mapper.on_key(Key::Char('\n')));
+
+    mapper.on_key(Key::Char(':'));
+    mapper.on_key(Key::Char('x'));
+    assert_eq!(vec!(OrchestratorEvent::EditInput(None), OrchestratorEvent::NewStatus("Invalid input: x, expected q <qty> or p <price|+Nt|-N%>".to_string())), mapper.on_key(Key::Char('\n')));
+    mapper.on_key(Key::Char(':'));
+    assert_eq!(vec!(OrchestratorEvent::EditInput(None)), mapper.on_key(Key::Esc));
+
+    // other keys are swallowed whilst the input line is open, incl. bound ones
+    mapper.on_key(Key::Char(':'));
+    mapper.on_key(Key::Char('q'));
+    for key in [Key::Up, Key::Down, Key::Left, Key::Ctrl('c'), Key::F(1)] {
+        assert_eq!(Vec::<OrchestratorEvent>::new(), mapper.on_key(key));
+    }
+    assert_eq!(vec!(OrchestratorEvent::EditInput(Some("q1".to_string()))), mapper.on_key(Key::Char('1')));
+    mapper.on_key(Key::Esc);
+    assert_eq!(vec!(OrchestratorEvent::Exit), mapper.on_key(Key::Ctrl('c')));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__validation() {
+    assert_eq!(Err(KeyBindingError::UnknownAction("buy-all".to_string())), KeyBindings::new(&actions(&[("buy-all", &["b"]), ("exit", &["esc"])])));
+    assert_eq!(Err(KeyBindingError::UnknownAction("cancel-0".to_string())), KeyBindings::new(&actions(&[("cancel-0", &["0"]), ("exit", &["esc"])])));
+    assert_eq!(Err(KeyBindingError::UnknownKey("ctrl-".to_string())), KeyBindings::new(&actions(&[("exit", &["ctrl-"])])));
+    assert_eq!(Err(KeyBindingError::UnknownKey("f13".to_string())), KeyBindings::new(&actions(&[("exit", &["f13"])])));
+    assert_eq!(Err(KeyBindingError::UnknownKey(" ".to_string())), KeyBindings::new(&actions(&[("exit", &[" "])])));
+    assert_eq!(Err(KeyBindingError::DuplicateBinding("z".to_string())), KeyBindings::new(&actions(&[("buy-bid", &["z"]), ("sell-ask", &["z"]), ("exit", &["esc"])])));
+    assert_eq!(Err(KeyBindingError::DuplicateBinding("z".to_string())), KeyBindings::new(&actions(&[("buy-bid", &["z", "z"]), ("exit", &["esc"])])));
+    assert_eq!(Err(KeyBindingError::ChordConflict("g".to_string(), "g b".to_string())), KeyBindings::new(&actions(&[("buy-bid", &["g"]), ("sell-ask", &["g b"]), ("exit", &["esc"])])));
+    assert_eq!(Err(KeyBindingError::MissingExit), KeyBindings::new(&actions(&[("buy-bid", &["z"])])));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__user_guide() {
+    let guide = KeyBindings::from_config(None).unwrap().user_guide();
+    let lines = guide.lines().collect::<Vec<&str>>();
+    assert_eq!(".-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-.", lines[0]);
+    assert_eq!("|             BITMEX WARRIOR            |", lines[2]);
+    assert_eq!("|  z -> buy @ bid                       |", lines[4]);
+    assert_eq!("|  +, = -> up qty                       |", lines[8]);
+    assert_eq!("|  1-9 -> cancel nth order              |", lines[21]);
+    assert!(lines.iter().all(|x| x.chars().count() == 41));
+    assert_eq!("`-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-=-'", *lines.last().unwrap());
+
+    // widens for longer lines
+    let guide = KeyBindings::new(&actions(&[("toggle-close-on-trigger", &["ctrl-k", "alt-k", "f1 f2 f3"]), ("exit", &["esc"])])).unwrap().user_guide();
+    let lines = guide.lines().collect::<Vec<&str>>();
+    assert_eq!("|  alt-k, ctrl-k, f1 f2 f3 -> toggle close on trigger   |", lines[4]);
+    assert_eq!("|  esc -> exit                                          |", lines[5]);
+    assert!(lines.iter().all(|x| x.chars().count() == lines[0].chars().count()));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keys__app_config() {
+    let template = fs::read_to_string("app.toml.template").unwrap();
+    let dir = env::temp_dir().join(format!("bitmex-warrior-keys-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_filename = dir.join("app.toml").to_str().unwrap().to_string();
+    fs::write(&config_filename, format!("{}\n[keys]\nbuy-bid = [\"B\", \"ctrl-b\"]\nexit = [\"q q\"]\n", template)).unwrap();
+    let cfg = AppConfig::new(&config_filename).unwrap();
+    let mut mapper = KeyMapper::new(KeyBindings::from_config(cfg.keys.as_ref()).unwrap());
+    assert_eq!(vec!(OrchestratorEvent::Buy(PriceType::Bid)), mapper.on_key(Key::Char('B')));
+    assert!(mapper.on_key(Key::Char('q')).is_empty());
+    assert_eq!(vec!(OrchestratorEvent::Exit), mapper.on_key(Key::Char('q')));
+    fs::remove_dir_all(dir).unwrap();
+}
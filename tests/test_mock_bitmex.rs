@@ -0,0 +1,233 @@
+mod support;
+
+use std::env;
+use std::fs;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use bitmex_warrior::exchange::{BitmexExchange, Exchange};
+use bitmex_warrior::model::*;
+use bitmex_warrior::orchestrator::process_event;
+use bitmex_warrior::recorder::{read_recording, recording_files, RecordingConfig};
+use bitmex_warrior::{rest, ws};
+
+use support::{MockBitmex, API_KEY, API_SECRET};
+use rust_decimal_macros::dec;
+use uuid::Uuid;
+
+fn order(cl_ord_id: &str) -> ExchangeOrder {
+    ExchangeOrder { cl_ord_id: cl_ord_id.to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(OrderType::Limit), price: Some(dec!(100.5)), qty: Some(dec!(10)), side: Some(Side::Buy), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: Some(TimeInForce::GoodTillCancel), exec_inst: Some(vec![ExecInst::ParticipateDoNotInitiate, ExecInst::ReduceOnly]) }
+}
+
+const HEARTBEAT: ws::Heartbeat = ws::Heartbeat { interval: Duration::from_millis(200), timeout: Duration::from_millis(200) };
+
+/// Collect events till the predicate holds, then close the channel, terminating handle_msgs.
+fn collect_events_till(rx: mpsc::Receiver<OrchestratorEvent>, is_done: impl Fn(&OrchestratorEvent) -> bool) -> Vec<OrchestratorEvent> {
+    let mut events = Vec::new();
+    loop {
+        let event = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let done = is_done(&event);
+        events.push(event);
+        if done {
+            return events
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__rest_orders() {
+    let mock = MockBitmex::start(vec![]);
+    let (url, key, secret) = (mock.http_url.as_str(), API_KEY, API_SECRET);
+
+    let issued = rest::issue_order(url, key, secret, "XBTUSD", &order("o1")).await.unwrap();
+    assert_eq!(issued, vec![OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, cum_qty: Some(dec!(0)), leaves_qty: Some(dec!(10)), .. order("o1") })]);
+    rest::issue_order(url, key, secret, "XBTUSD", &order("o2")).await.unwrap();
+
+    let amendment = ExchangeOrder { price: Some(dec!(101)), ord_type: None, qty: None, side: None, time_in_force: None, exec_inst: None, .. order("o1") };
+    match rest::amend_order(url, key, secret, &amendment).await.unwrap() {
+        OrchestratorEvent::UpdateOrder(amended) => assert_eq!(amended.price, Some(dec!(101))),
+        other => panic!("Unexpected event: {:?}", other)
+    }
+
+    let canceled = rest::cancel_orders(url, key, secret, &["o1", "o2"]).await.unwrap();
+    assert_eq!(canceled.len(), 2);
+    assert!(canceled.iter().all(|x| matches!(x, OrchestratorEvent::UpdateOrder(o) if o.ord_status == OrderStatus::Canceled)));
+    assert_eq!(rest::cancel_orders(url, key, secret, &["o1"]).await.unwrap(), vec![OrchestratorEvent::UpdateOrder(ExchangeOrder {
+        ord_status: OrderStatus::Canceled, price: Some(dec!(101)), cum_qty: Some(dec!(0)), leaves_qty: Some(dec!(10)), .. order("o1") })]);
+
+    // all signatures verify, incl. of url encoded execInst
+    let requests = mock.requests.lock().unwrap().clone();
+    assert_eq!(requests.iter().map(|x| x.method.as_str()).collect::<Vec<_>>(), vec!["POST", "POST", "PUT", "DELETE", "DELETE"]);
+    assert!(requests.iter().all(|x| x.is_authenticated && x.path == "/api/v1/order"));
+    assert!(requests[0].body.contains("execInst=ParticipateDoNotInitiate%2CReduceOnly"));
+    assert_eq!(requests[3].body, "clOrdID=%5B%22o1%22%2C%22o2%22%5D");
+    assert_eq!(requests[4].body, "clOrdID=o1");
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__rest_bad_signature() {
+    let mock = MockBitmex::start(vec![]);
+    match rest::issue_order(&mock.http_url, API_KEY, "wrong-secret", "XBTUSD", &order("o1")).await.unwrap().as_slice() {
+        [OrchestratorEvent::UpdateOrder(rejected), OrchestratorEvent::NewStatus(status)] => {
+            assert_eq!(*rejected, ExchangeOrder::with_status("o1", OrderStatus::Rejected));
+            assert!(status.contains("401") && status.contains("Signature not valid."));
+        }
+        other => panic!("Unexpected events: {:?}", other)
+    }
+    assert!(!mock.requests.lock().unwrap()[0].is_authenticated);
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__rest_order_rejected() {
+    let mock = MockBitmex::start(vec![]);
+    let mut exchange = BitmexExchange { http_url: mock.http_url.clone(), wss_url: mock.wss_url.clone(), api_key: API_KEY.to_string(), api_secret: API_SECRET.to_string(), symbol: "XBTUSD".to_string(), subscriptions: vec![], heartbeat: HEARTBEAT, recording: None };
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.add_order(ExchangeOrder { price: Some(dec!(-1)), .. order("o1") });
+
+    // 400, the order dropped rather than left NotYetIssued
+    let events = exchange.execute(&ExchangeCmd::IssueOrder(state.orders["o1"].clone())).await.unwrap();
+    assert!(matches!(events.as_slice(), [OrchestratorEvent::UpdateOrder(_), OrchestratorEvent::NewStatus(status)] if status.contains("400") && status.contains("Invalid price")), "{:?}", events);
+    events.iter().for_each(|x| { process_event(x, state); });
+    assert!(state.orders.is_empty());
+    assert!(state.status.contains("Invalid price"));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_mock_bitmex__ws_subscribe_and_push() {
+    let mock = MockBitmex::start(vec![
+        r#"{"table":"orderBook10","action":"partial","data":[{"symbol":"XBTUSD","bids":[[100,10],[99.5,20]],"asks":[[100.5,30]],"timestamp":"2020-01-01T00:00:00Z"}]}"#.to_string(),
+        r#"{"table":"trade","action":"insert","data":[{"timestamp":"2020-01-01T00:00:01Z","symbol":"XBTUSD","side":"Sell","size":5,"price":100,"tickDirection":"MinusTick","trdMatchID":"00000000-0000-0000-0000-000000000001","grossValue":5000,"homeNotional":0.05,"foreignNotional":5}]}"#.to_string(),
+    ]);
+    let (tx, rx) = mpsc::channel();
+    let (_stop_tx, stop_rx) = mpsc::channel();
+    let (url, subscriptions) = (mock.wss_url.clone(), vec!["orderBook10:XBTUSD".to_string(), "trade:XBTUSD".to_string()]);
+    let ws_thread = thread::spawn(move || ws::handle_msgs(&url, API_KEY, API_SECRET, subscriptions, HEARTBEAT, None, tx, stop_rx));
+
+    let events = collect_events_till(rx, |x| matches!(x, OrchestratorEvent::NewTrades(_)));
+    ws_thread.join().unwrap();
+
+    assert_eq!(&events[..2], &[OrchestratorEvent::NewConnectionState(ConnectionState::Connecting), OrchestratorEvent::NewConnectionState(ConnectionState::Connected)]);
+    assert!(events.contains(&OrchestratorEvent::NewBid(dec!(100))));
+    assert!(events.contains(&OrchestratorEvent::NewAsk(dec!(100.5))));
+    assert!(events.contains(&OrchestratorEvent::NewDepth(vec![Level { price: dec!(100), size: dec!(10) }, Level { price: dec!(99.5), size: dec!(20) }], vec![Level { price: dec!(100.5), size: dec!(30) }])));
+    match events.last() {
+        Some(OrchestratorEvent::NewTrades(trades)) => assert_eq!((trades[0].side, trades[0].size, trades[0].price), (Side::Sell, dec!(5), dec!(100))),
+        other => panic!("Unexpected event: {:?}", other)
+    }
+    assert_eq!(*mock.subscriptions.lock().unwrap(), vec!["orderBook10:XBTUSD", "trade:XBTUSD"]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_mock_bitmex__ws_bad_signature() {
+    let mock = MockBitmex::start(vec![]);
+    let (tx, rx) = mpsc::channel();
+    let (_stop_tx, stop_rx) = mpsc::channel();
+    let url = mock.wss_url.clone();
+    let ws_thread = thread::spawn(move || ws::handle_msgs(&url, API_KEY, "wrong-secret", vec!["order:XBTUSD".to_string()], HEARTBEAT, None, tx, stop_rx));
+
+    // auth is rejected with an error and the connection closed, prompting a reconnect
+    let events = collect_events_till(rx, |x| *x == OrchestratorEvent::NewConnectionState(ConnectionState::Disconnected));
+    ws_thread.join().unwrap();
+    assert_eq!(events, vec![
+        OrchestratorEvent::NewConnectionState(ConnectionState::Connecting),
+        OrchestratorEvent::NewConnectionState(ConnectionState::Connected),
+        OrchestratorEvent::NewStatus("Info on: Welcome to the BitMEX Realtime API.".to_string()),
+        OrchestratorEvent::NewStatus("Error on: \"Signature not valid.\"".to_string()),
+        OrchestratorEvent::NewConnectionState(ConnectionState::Disconnected)]);
+    assert!(mock.subscriptions.lock().unwrap().is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_mock_bitmex__recording_finished_on_stop() {
+    let pushes = vec![
+        r#"{"table":"orderBook10","action":"partial","data":[{"symbol":"XBTUSD","bids":[[100,10]],"asks":[[100.5,30]],"timestamp":"2020-01-01T00:00:00Z"}]}"#.to_string(),
+        r#"{"table":"orderBook10","action":"update","data":[{"symbol":"XBTUSD","bids":[[100,20]],"asks":[[100.5,30]],"timestamp":"2020-01-01T00:00:01Z"}]}"#.to_string(),
+    ];
+    let mock = MockBitmex::start(pushes.clone());
+    let dir = env::temp_dir().join(format!("bitmex-warrior-ws-recording-{}", Uuid::new_v4()));
+    let recording = RecordingConfig { dir: dir.to_str().unwrap().to_string(), max_file_bytes: 1_000_000, compress: true };
+    let exchange = BitmexExchange { http_url: mock.http_url.clone(), wss_url: mock.wss_url.clone(), api_key: API_KEY.to_string(), api_secret: API_SECRET.to_string(), symbol: "XBTUSD".to_string(), subscriptions: vec!["orderBook10:XBTUSD".to_string()], heartbeat: HEARTBEAT, recording: Some(recording) };
+    let (tx, rx) = mpsc::channel();
+    let subscription = exchange.subscribe(tx);
+    let mut depth_count = 0;
+    while depth_count < 2 {
+        if let OrchestratorEvent::NewDepth(..) = rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+            depth_count += 1;
+        }
+    }
+
+    // stopped with the orchestrator channel still open, the last compressed file is readable in full
+    subscription.stop().unwrap();
+    let files = recording_files(&dir).unwrap();
+    assert_eq!(files.len(), 1);
+    let frames = read_recording(&files[0]).unwrap().map(|x| x.unwrap().frame).collect::<Vec<_>>();
+    assert!(frames.ends_with(&pushes), "{:?}", frames);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__instrument() {
+    let mock = MockBitmex::start(vec![]);
+    assert_eq!(rest::get_instrument(&mock.http_url, "XBTUSD").await.unwrap(), OrchestratorEvent::NewInstrument(Instrument {
+        symbol: "XBTUSD".to_string(), tick_size: dec!(0.5), lot_size: dec!(100), multiplier: dec!(-100000000), is_inverse: true, max_order_qty: Some(dec!(10000000)) }));
+    assert_eq!(rest::get_instrument(&mock.http_url, "XBTEUR").await.unwrap(), OrchestratorEvent::NewStatus("Unknown instrument XBTEUR, retaining configured tick size".to_string()));
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__cancel_all_after() {
+    let mock = MockBitmex::start(vec![]);
+    let cancel_at = match rest::cancel_all_after(&mock.http_url, API_KEY, API_SECRET, Duration::from_secs(60)).await.unwrap() {
+        OrchestratorEvent::UpdateDeadMansSwitch(Some(cancel_at)) => cancel_at,
+        other => panic!("Unexpected event: {:?}", other)
+    };
+    assert!(cancel_at > chrono::Utc::now() + chrono::Duration::seconds(55));
+    assert_eq!(rest::cancel_all_after(&mock.http_url, API_KEY, API_SECRET, Duration::ZERO).await.unwrap(), OrchestratorEvent::UpdateDeadMansSwitch(None));
+    assert_eq!(rest::cancel_all_after(&mock.http_url, API_KEY, "bad secret", Duration::ZERO).await.unwrap(),
+               OrchestratorEvent::NewStatus(r#"Received unexpected http response status 401 Unauthorized on cancelAllAfter: "{\"error\":{\"message\":\"Signature not valid.\",\"name\":\"HTTPError\"}}""#.to_string()));
+
+    let requests = mock.requests.lock().unwrap().clone();
+    assert_eq!(requests.iter().map(|x| (x.method.as_str(), x.path.as_str(), x.body.as_str(), x.is_authenticated)).collect::<Vec<_>>(), vec![
+        ("POST", "/api/v1/order/cancelAllAfter", "timeout=60000", true),
+        ("POST", "/api/v1/order/cancelAllAfter", "timeout=0", true),
+        ("POST", "/api/v1/order/cancelAllAfter", "timeout=0", false),
+    ]);
+}
+
+#[tokio::test]
+#[allow(non_snake_case)]
+async fn test_mock_bitmex__flatten_all() {
+    let mock = MockBitmex::start(vec![]);
+    rest::issue_order(&mock.http_url, API_KEY, API_SECRET, "XBTUSD", &order("o1")).await.unwrap();
+    rest::issue_order(&mock.http_url, API_KEY, API_SECRET, "XBTUSD", &order("o2")).await.unwrap();
+
+    // orders placed elsewhere, ie. unknown to the state, are canceled all the same
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let close_order = match process_event(&OrchestratorEvent::FlattenAll, state) {
+        Some(ExchangeCmd::CancelAllAndClose(o)) => o,
+        other => panic!("Unexpected cmd: {:?}", other)
+    };
+    let mut exchange = BitmexExchange { http_url: mock.http_url.clone(), wss_url: mock.wss_url.clone(), api_key: API_KEY.to_string(), api_secret: API_SECRET.to_string(), symbol: "XBTUSD".to_string(), subscriptions: vec![], heartbeat: HEARTBEAT, recording: None };
+    let events = exchange.execute(&ExchangeCmd::CancelAllAndClose(close_order.clone())).await.unwrap();
+    let canceled = events.iter().filter_map(|x| match x {
+        OrchestratorEvent::UpdateOrder(o) if o.ord_status == OrderStatus::Canceled => Some(o.cl_ord_id.as_str()),
+        _ => None
+    }).collect::<Vec<&str>>();
+    assert_eq!(canceled.len(), 2);
+    assert!(canceled.contains(&"o1") && canceled.contains(&"o2"));
+    assert_eq!(events.last(), Some(&OrchestratorEvent::NewStatus("Flatten all done: canceled 2 orders, close order Filled, filled 100/100 @ avg -".to_string())));
+
+    let requests = mock.requests.lock().unwrap().clone();
+    assert!(requests.iter().all(|x| x.is_authenticated));
+    assert_eq!((requests[2].method.as_str(), requests[2].path.as_str(), requests[2].body.as_str()), ("DELETE", "/api/v1/order/all", "symbol=XBTUSD"));
+    assert_eq!(requests[3].body, format!("symbol=XBTUSD&ordType=Market&timeInForce=ImmediateOrCancel&execInst=Close&clOrdID={}", close_order.cl_ord_id));
+}
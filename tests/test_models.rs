@@ -1,14 +1,13 @@
 use chrono::prelude::*;
-use serde_json;
 
 use bitmex_warrior::model::*;
 use bitmex_warrior::rest_model;
 use bitmex_warrior::rest_model::Order;
 use bitmex_warrior::ws_model;
-use bitmex_warrior::ws_model::{OrderBookRow, OrderRow, Table, TableAction};
+use bitmex_warrior::ws_model::{ExecutionRow, OrderBookRow, OrderRow, PositionRow, Table, TableAction};
+use rust_decimal_macros::dec;
 
 #[cfg(test)]
-
 #[allow(non_upper_case_globals)]
 const ts_str: &str = "2020-01-01T00:00:00Z";
 
@@ -17,7 +16,7 @@ fn test_ws_model() {
     let ts= ts_str.parse::<DateTime<Utc>>().unwrap();
     assert_eq!(
         serde_json::from_str::<ws_model::Response>(r#"{"info": "some_info", "version": "v1", "timestamp": "2020-01-01T00:00:00Z"}"#).unwrap(),
-        ws_model::Response::Info{ info: "some_info".to_string(), version: "v1".to_string(), timestamp: ts.clone() }
+        ws_model::Response::Info{ info: "some_info".to_string(), version: "v1".to_string(), timestamp: ts }
     );
     assert_eq!(
         serde_json::from_str::<ws_model::Response>(r#"{"subscribe": "trade", "success": true}"#).unwrap(),
@@ -25,15 +24,23 @@ fn test_ws_model() {
     );
     assert_eq!(
         serde_json::from_str::<ws_model::Response>(r#"{"table": "orderBook10", "action": "update", "data": [{"timestamp": "2020-01-01T00:00:00Z", "symbol": "XBTUSD", "asks": [[1.1, 2.2], [3.3, 4.4]], "bids": [[8.8, 9.9]] }]}"#).unwrap(),
-        ws_model::Response::Table(Table::OrderBook10 { action: TableAction::Update, data: vec![OrderBookRow{ timestamp: ts.clone(), symbol: "XBTUSD".to_string(), asks: vec![[1.1, 2.2], [3.3, 4.4]], bids: vec![[8.8, 9.9]] }] })
+        ws_model::Response::Table(Table::OrderBook10 { action: TableAction::Update, data: vec![OrderBookRow{ timestamp: ts, symbol: "XBTUSD".to_string(), asks: vec![[dec!(1.1), dec!(2.2)], [dec!(3.3), dec!(4.4)]], bids: vec![[dec!(8.8), dec!(9.9)]] }] })
     );
     assert_eq!(
         serde_json::from_str::<ws_model::Response>(r#"{"table":"orderBook10","action":"update","keys":["symbol"],"types":{"symbol":"symbol","bids":"","asks":"","timestamp":"timestamp"},"foreignKeys":{"symbol":"instrument"},"attributes":{"symbol":"sorted"},"filter":{"symbol":"XBTUSD"},"data":[{"symbol":"XBTUSD","bids":[[51189,112760],[51188.5,2],[51187,2000],[51182,12000],[51180,700],[51178.5,88500],[51178,188],[51177,5000],[51176,1694],[51175.5,1250]],"asks":[[51189.5,903974],[51190,59762],[51190.5,1005095],[51192,10000],[51193,24953],[51193.5,200000],[51194,73398],[51195,100],[51195.5,2100],[51196,303]],"timestamp":"2020-01-01T00:00:00Z"}]}"#).unwrap(),
-        ws_model::Response::Table(Table::OrderBook10 { action: TableAction::Update, data: vec![OrderBookRow{ timestamp: ts.clone(), symbol: "XBTUSD".to_string(), asks: vec![[51189.5, 903974.0], [51190.0, 59762.0], [51190.5, 1005095.0], [51192.0, 10000.0], [51193.0, 24953.0], [51193.5, 200000.0], [51194.0, 73398.0], [51195.0, 100.0], [51195.5, 2100.0], [51196.0, 303.0]], bids: vec![[51189.0, 112760.0], [51188.5, 2.0], [51187.0, 2000.0], [51182.0, 12000.0], [51180.0, 700.0], [51178.5, 88500.0], [51178.0, 188.0], [51177.0, 5000.0], [51176.0, 1694.0], [51175.5, 1250.0]] }] })
+        ws_model::Response::Table(Table::OrderBook10 { action: TableAction::Update, data: vec![OrderBookRow{ timestamp: ts, symbol: "XBTUSD".to_string(), asks: vec![[dec!(51189.5), dec!(903974)], [dec!(51190), dec!(59762)], [dec!(51190.5), dec!(1005095)], [dec!(51192), dec!(10000)], [dec!(51193), dec!(24953)], [dec!(51193.5), dec!(200000)], [dec!(51194), dec!(73398)], [dec!(51195), dec!(100)], [dec!(51195.5), dec!(2100)], [dec!(51196), dec!(303)]], bids: vec![[dec!(51189), dec!(112760)], [dec!(51188.5), dec!(2)], [dec!(51187), dec!(2000)], [dec!(51182), dec!(12000)], [dec!(51180), dec!(700)], [dec!(51178.5), dec!(88500)], [dec!(51178), dec!(188)], [dec!(51177), dec!(5000)], [dec!(51176), dec!(1694)], [dec!(51175.5), dec!(1250)]] }] })
     );
     assert_eq!(
         serde_json::from_str::<ws_model::Response>(r#"{"table":"order","action":"insert","data":[{"orderID":"8743ca01-d400-4799-a229-6e9ee72dd2b5","clOrdID":"xxx","clOrdLinkID":"","account":1502286,"symbol":"XBTUSD","side":"Buy","simpleOrderQty":null,"orderQty":10,"price":50097.5,"displayQty":null,"stopPx":null,"pegOffsetValue":null,"pegPriceType":"","currency":"USD","settlCurrency":"XBt","ordType":"Limit","timeInForce":"GoodTillCancel","execInst":"ParticipateDoNotInitiate","contingencyType":"","exDestination":"XBME","ordStatus":"New","triggered":"","workingIndicator":true,"ordRejReason":"","simpleLeavesQty":null,"leavesQty":10,"simpleCumQty":null,"cumQty":0,"avgPx":null,"multiLegReportingType":"SingleSecurity","text":"Submission from www.bitmex.com","transactTime":"2020-01-01T00:00:00Z","timestamp":"2020-01-01T00:00:00Z"}]}"#).unwrap(),
-        ws_model::Response::Table(Table::Order { action: TableAction::Insert, data: vec![OrderRow { timestamp: Some(ts), symbol: "XBTUSD".to_string(), cl_ord_id: "xxx".to_string(), side: Some(Side::Buy), ord_status: OrderStatus::New, ord_type: Some(OrderType::Limit), order_qty: Some(10.0), price: Some(50097.5) }] })
+        ws_model::Response::Table(Table::Order { action: TableAction::Insert, data: vec![OrderRow { timestamp: Some(ts), symbol: "XBTUSD".to_string(), cl_ord_id: "xxx".to_string(), side: Some(Side::Buy), ord_status: OrderStatus::New, ord_type: Some(OrderType::Limit), order_qty: Some(dec!(10)), price: Some(dec!(50097.5)), stop_px: None, cum_qty: Some(dec!(0)), leaves_qty: Some(dec!(10)), avg_px: None, time_in_force: Some(TimeInForce::GoodTillCancel), exec_inst: Some("ParticipateDoNotInitiate".to_string()) }] })
+    );
+    assert_eq!(
+        serde_json::from_str::<ws_model::Response>(r#"{"table":"position","action":"update","data":[{"account":299045,"symbol":"XBTUSD","currency":"XBt","currentQty":100,"avgEntryPrice":51124.5,"liquidationPrice":100,"unrealisedPnl":-3910,"realisedPnl":-1120,"leverage":100,"timestamp":"2020-01-01T00:00:00Z"}]}"#).unwrap(),
+        ws_model::Response::Table(Table::Position { action: TableAction::Update, data: vec![PositionRow { symbol: "XBTUSD".to_string(), current_qty: Some(dec!(100)), avg_entry_price: Some(dec!(51124.5)), liquidation_price: Some(dec!(100)), unrealised_pnl: Some(dec!(-3910)), realised_pnl: Some(dec!(-1120)), leverage: Some(dec!(100)), mark_price: None }] })
+    );
+    assert_eq!(
+        serde_json::from_str::<ws_model::Response>(r#"{"table":"execution","action":"insert","data":[{"execID":"0b8ad8a4-4e4c-2ce4-2d5a-2ff3b1a4a0c1","orderID":"8743ca01-d400-4799-a229-6e9ee72dd2b5","clOrdID":"xxx","account":299045,"symbol":"XBTUSD","side":"Buy","lastQty":40,"lastPx":51234.5,"orderQty":100,"price":51234.5,"execType":"Trade","ordType":"Limit","ordStatus":"PartiallyFilled","leavesQty":60,"cumQty":40,"avgPx":51234.5,"commission":0.0005,"timestamp":"2020-01-01T00:00:00Z"}]}"#).unwrap(),
+        ws_model::Response::Table(Table::Execution { action: TableAction::Insert, data: vec![ExecutionRow { timestamp: Some(ts), symbol: "XBTUSD".to_string(), exec_id: "0b8ad8a4-4e4c-2ce4-2d5a-2ff3b1a4a0c1".to_string(), cl_ord_id: "xxx".to_string(), side: Some(Side::Buy), exec_type: "Trade".to_string(), ord_status: Some(OrderStatus::PartiallyFilled), last_qty: Some(dec!(40)), last_px: Some(dec!(51234.5)), cum_qty: Some(dec!(40)), leaves_qty: Some(dec!(60)), avg_px: Some(dec!(51234.5)) }] })
     );
 }
 
@@ -41,6 +48,44 @@ fn test_ws_model() {
 fn test_rest_model() {
     assert_eq!(
         serde_json::from_str::<rest_model::Response>(r#"{"orderID":"e4f3f392-c2d0-4e4d-8e69-a57268431ea7","clOrdID":"4b2322e7-1e50-409c-80d7-ce894b7a9139","clOrdLinkID":"","account":299045,"symbol":"XBTUSD","side":"Buy","simpleOrderQty":null,"orderQty":100,"price":51170.5,"displayQty":null,"stopPx":null,"pegOffsetValue":null,"pegPriceType":"","currency":"USD","settlCurrency":"XBt","ordType":"Limit","timeInForce":"GoodTillCancel","execInst":"","contingencyType":"","exDestination":"XBME","ordStatus":"Filled","triggered":"","workingIndicator":false,"ordRejReason":"","simpleLeavesQty":null,"leavesQty":0,"simpleCumQty":null,"cumQty":100,"avgPx":51124.7444,"multiLegReportingType":"SingleSecurity","text":"Submitted via API.","transactTime":"2021-02-24T12:21:59.150Z","timestamp":"2021-02-24T12:21:59.150Z"}"#).unwrap(),
-        rest_model::Response::Order(Order { cl_ord_id: "4b2322e7-1e50-409c-80d7-ce894b7a9139".to_string(), symbol: "XBTUSD".to_string(), side: Side::Buy, order_qty: 100.0, ord_status: OrderStatus::Filled, ord_type: Some(OrderType::Limit), price: 51170.5 })
+        rest_model::Response::Order(Order { cl_ord_id: "4b2322e7-1e50-409c-80d7-ce894b7a9139".to_string(), symbol: "XBTUSD".to_string(), side: Side::Buy, order_qty: dec!(100), ord_status: OrderStatus::Filled, ord_type: Some(OrderType::Limit), price: Some(dec!(51170.5)), stop_px: None, cum_qty: Some(dec!(100)), leaves_qty: Some(dec!(0)), avg_px: Some(dec!(51124.7444)), time_in_force: Some(TimeInForce::GoodTillCancel), exec_inst: Some("".to_string()) })
     );
-}
\ No newline at end of file
+}
+#[test]
+#[allow(non_snake_case)]
+fn test_rest_model__exact_round_trip() {
+    let json = r#"{"clOrdID":"xxx","symbol":"XBTUSD","side":"Sell","orderQty":0.0001,"ordStatus":"New","ordType":"Limit","price":0.00001235,"stopPx":51124.7444,"cumQty":0,"leavesQty":0.0001,"avgPx":51124.744412345678901234,"timeInForce":"GoodTillCancel","execInst":""}"#;
+    let order = serde_json::from_str::<Order>(json).unwrap();
+    assert_eq!((order.order_qty, order.price, order.stop_px, order.avg_px), (dec!(0.0001), Some(dec!(0.00001235)), Some(dec!(51124.7444)), Some(dec!(51124.744412345678901234))));
+    assert_eq!(order, serde_json::from_str::<Order>(&serde_json::to_string(&order).unwrap()).unwrap());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_input__parse() {
+    assert_eq!(Some(PriceInput::Absolute(dec!(50123.5))), PriceInput::parse("50123.5"));
+    assert_eq!(Some(PriceInput::Ticks(2)), PriceInput::parse("+2t"));
+    assert_eq!(Some(PriceInput::Ticks(-3)), PriceInput::parse("-3t"));
+    assert_eq!(Some(PriceInput::Percent(dec!(-0.1))), PriceInput::parse("-0.1%"));
+    assert_eq!(None, PriceInput::parse("2t"));
+    assert_eq!(None, PriceInput::parse("+1.5t"));
+    assert_eq!(None, PriceInput::parse("-100"));
+    assert_eq!(None, PriceInput::parse("NaN"));
+    assert_eq!("+2t / -0.1% / 100.5", format!("{} / {} / {}", PriceInput::Ticks(2), PriceInput::Percent(dec!(-0.1)), PriceInput::Absolute(dec!(100.5))));
+
+    assert_eq!(Ok(OrchestratorEvent::SetQty(dec!(250))), OrchestratorEvent::parse_input("q 250"));
+    assert_eq!(Ok(OrchestratorEvent::SetPrice(Some(PriceInput::Ticks(2)))), OrchestratorEvent::parse_input(" p  +2t "));
+    assert_eq!(Ok(OrchestratorEvent::SetPrice(None)), OrchestratorEvent::parse_input("p"));
+    assert_eq!(Err("Invalid qty: lots".to_string()), OrchestratorEvent::parse_input("q lots"));
+    assert_eq!(Err("Invalid price: +2x".to_string()), OrchestratorEvent::parse_input("p +2x"));
+    assert!(OrchestratorEvent::parse_input("q 1 2").is_err());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_round_to_step() {
+    assert_eq!(dec!(51170.5), round_to_step(dec!(51170.50000001), dec!(0.5)));
+    assert_eq!("0.3", round_to_step(dec!(0.1) + dec!(0.2), dec!(0.1)).to_string());
+    assert_eq!(dec!(300), round_to_step(dec!(250), dec!(100)));
+    assert_eq!(dec!(0.00001235), round_to_step(dec!(0.0000123456), dec!(0.00000005)));
+}
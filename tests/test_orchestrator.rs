@@ -1,17 +1,380 @@
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use chrono::Utc;
+use uuid::Uuid;
+
 use bitmex_warrior::model::*;
 use bitmex_warrior::orchestrator::process_event;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
 
-#[cfg(test)]
+/// State with the feed up, at the given touch.
+fn connect(mut state: State, bid: Decimal, ask: Decimal) -> State {
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), &mut state);
+    process_event(&OrchestratorEvent::NewBid(bid), &mut state);
+    process_event(&OrchestratorEvent::NewAsk(ask), &mut state);
+    state
+}
+
+fn connected_state() -> State {
+    connect(State::new(dec!(10), dec!(1), dec!(0.5), dec!(5)), dec!(100), dec!(101))
+}
+
+/// The order issued on the event, panicking on any other outcome.
+fn issue(event: OrchestratorEvent, state: &mut State) -> ExchangeOrder {
+    match process_event(&event, state) {
+        Some(ExchangeCmd::IssueOrder(o)) => o,
+        other => panic!("Unexpected cmd: {:?}", other)
+    }
+}
 
+/// Amend the last order on the event, acked by the exchange as is.
+fn ack_amend(event: OrchestratorEvent, state: &mut State) {
+    match process_event(&event, state) {
+        Some(ExchangeCmd::AmendOrder(o)) => { process_event(&OrchestratorEvent::UpdateOrder(o), state); }
+        other => panic!("Unexpected cmd: {:?}", other)
+    }
+}
+
+#[cfg(test)]
 #[test]
 #[allow(non_snake_case)]
 fn test_process_events__up_down_qty() {
-    let state = &mut State::new(10.0, 1.0);
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
     let effect1 = process_event(&OrchestratorEvent::UpQty, &mut *state);
     assert_eq!(effect1, None::<ExchangeCmd<'_>>);
-    assert_eq!(state.qty, 11.0);
+    assert_eq!(state.qty, dec!(11));
     process_event(&OrchestratorEvent::DownQty, &mut *state);
     let effect2 = process_event(&OrchestratorEvent::DownQty, state);
     assert_eq!(effect2, None::<ExchangeCmd<'_>>);
-    assert_eq!(state.qty, 9.0);
-}
\ No newline at end of file
+    assert_eq!(state.qty, dec!(9));
+}
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__concurrent_orders() {
+    let state = &mut connected_state();
+    let bid_order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    let ask_order = issue(OrchestratorEvent::Sell(PriceType::Ask), state);
+    assert_eq!(state.live_orders().map(|x| x.cl_ord_id.as_str()).collect::<Vec<&str>>(), vec![bid_order.cl_ord_id.as_str(), ask_order.cl_ord_id.as_str()]);
+
+    // partial update is routed to the right order, retaining unspecified fields
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { cl_ord_id: bid_order.cl_ord_id.clone(), ord_status: OrderStatus::New, ord_type: None, price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }), state);
+    assert_eq!(state.orders[&bid_order.cl_ord_id], ExchangeOrder { ord_status: OrderStatus::New, .. bid_order.clone() });
+    assert_eq!(state.orders[&ask_order.cl_ord_id], ask_order);
+
+    assert_eq!(process_event(&OrchestratorEvent::CancelLast, state), Some(ExchangeCmd::CancelOrder(&ask_order.cl_ord_id)));
+    assert_eq!(process_event(&OrchestratorEvent::CancelAt(1), state), Some(ExchangeCmd::CancelOrder(&bid_order.cl_ord_id)));
+    assert_eq!(process_event(&OrchestratorEvent::CancelAt(3), state), None);
+    assert_eq!(process_event(&OrchestratorEvent::CancelAll, state), Some(ExchangeCmd::CancelOrders(vec![&bid_order.cl_ord_id, &ask_order.cl_ord_id])));
+
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { cl_ord_id: ask_order.cl_ord_id.clone(), ord_status: OrderStatus::Canceled, ord_type: None, price: None, qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }), state);
+    assert_eq!(state.live_orders().count(), 1);
+    assert_eq!(state.last_order(), Some(&ExchangeOrder { ord_status: OrderStatus::New, .. bid_order }));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__fills() {
+    let state = &mut connected_state();
+    state.qty = dec!(100);
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    let fill = |exec_id: &str, last_qty: Decimal, last_px: Decimal, cum_qty: Decimal| OrchestratorEvent::NewFill(Fill { cl_ord_id: order.cl_ord_id.clone(), exec_id: exec_id.to_string(), side: Side::Buy, last_qty, last_px, cum_qty: Some(cum_qty) });
+
+    process_event(&fill("1", dec!(40), dec!(100), dec!(40)), state);
+    process_event(&fill("2", dec!(20), dec!(97), dec!(60)), state);
+    process_event(&fill("2", dec!(20), dec!(97), dec!(60)), state);  // duplicate, ignored
+    let curr_order = &state.orders[&order.cl_ord_id];
+    assert_eq!(curr_order.ord_status, OrderStatus::PartiallyFilled);
+    assert_eq!((curr_order.cum_qty, curr_order.leaves_qty, curr_order.avg_px), (Some(dec!(60)), Some(dec!(40)), Some(dec!(99))));
+    assert_eq!(curr_order.fill_desc(), "filled 60/100 @ avg 99");
+
+    process_event(&fill("3", dec!(40), dec!(99), dec!(100)), state);
+    assert_eq!(state.live_orders().count(), 0);
+    assert_eq!(state.status, format!("Filled Buy Limit order: {} filled 100/100 @ avg 99", order.cl_ord_id));
+    assert_eq!((state.risk.pnl.position_qty, state.risk.pnl.avg_entry_value), (dec!(100), dec!(99)));  // sans the duplicate
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__fills_of_untracked_orders() {
+    let state = &mut connected_state();
+    state.qty = dec!(100);
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    let fill = |cl_ord_id: &str, exec_id: &str, side: Side, last_qty: Decimal, cum_qty: Decimal| OrchestratorEvent::NewFill(Fill { cl_ord_id: cl_ord_id.to_string(), exec_id: exec_id.to_string(), side, last_qty, last_px: dec!(100), cum_qty: Some(cum_qty) });
+
+    // the order update beating the execution, the order being removed as Filled
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::Filled, cum_qty: Some(dec!(100)), leaves_qty: Some(dec!(0)), avg_px: Some(dec!(100)), ..order.clone() }), state);
+    assert!(state.orders.is_empty());
+    assert_eq!(process_event(&fill(&order.cl_ord_id, "1", Side::Buy, dec!(100), dec!(100)), state), None);
+    assert_eq!(state.risk.pnl.position_qty, dec!(100));
+
+    // eg. the close order of flatten all, never tracked
+    assert_eq!(process_event(&fill("close", "2", Side::Sell, dec!(60), dec!(60)), state), None);
+    assert_eq!(state.risk.pnl.position_qty, dec!(40));
+    assert!(state.orders.is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__amend_price() {
+    let state = &mut connected_state();
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    // cannot amend till acknowledged by the exchange
+    assert_eq!(process_event(&OrchestratorEvent::UpLastPrice, state), None);
+
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, .. order.clone() }), state);
+    let amend = |price: Decimal| Some(ExchangeCmd::AmendOrder(ExchangeOrder { cl_ord_id: order.cl_ord_id.clone(), ord_status: OrderStatus::New, ord_type: None, price: Some(price), qty: None, side: None, cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None }));
+    assert_eq!(process_event(&OrchestratorEvent::UpLastPrice, state), amend(dec!(100.5)));
+    // priced off the acked order, till the exchange acks the amend
+    assert_eq!(state.orders[&order.cl_ord_id].price, Some(dec!(100)));
+    assert_eq!(process_event(&OrchestratorEvent::UpLastPrice, state), amend(dec!(100.5)));
+    ack_amend(OrchestratorEvent::UpLastPrice, state);
+    assert_eq!(process_event(&OrchestratorEvent::UpLastPrice, state), amend(dec!(101)));
+    ack_amend(OrchestratorEvent::UpLastPrice, state);
+    ack_amend(OrchestratorEvent::DownLastPrice, state);
+    assert_eq!(state.orders[&order.cl_ord_id].price, Some(dec!(100.5)));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__rejected_amend() {
+    let state = &mut connected_state();
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, .. order.clone() }), state);
+    assert!(matches!(process_event(&OrchestratorEvent::UpLastPrice, state), Some(ExchangeCmd::AmendOrder(_))));
+    // as surfaced by rest::amend_order
+    process_event(&OrchestratorEvent::NewStatus("Invalid price".to_string()), state);
+    assert_eq!(state.orders[&order.cl_ord_id].price, Some(dec!(100)));
+    assert_eq!(state.orders[&order.cl_ord_id].ord_status, OrderStatus::New);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__no_tick_drift() {
+    let state = &mut connect(State::new(dec!(10), dec!(1), dec!(0.1), dec!(5)), dec!(0.3), dec!(0.4));
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, .. order.clone() }), state);
+    for _ in 0..1000 {
+        ack_amend(OrchestratorEvent::UpLastPrice, state);
+    }
+    assert_eq!(state.orders[&order.cl_ord_id].price.unwrap().to_string(), "100.3");
+    for _ in 0..1000 {
+        ack_amend(OrchestratorEvent::DownLastPrice, state);
+    }
+    assert_eq!(state.orders[&order.cl_ord_id].price.unwrap().to_string(), "0.3");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__conditional_orders() {
+    let state = &mut connected_state();
+    let issued = |event: OrchestratorEvent, state: &mut State| {
+        let o = issue(event, state);
+        (o.ord_type.unwrap(), o.price, o.stop_px)
+    };
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Bid), state), (OrderType::Market, Some(dec!(100)), None));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Buy(PriceType::Ask), state), (OrderType::Stop, None, Some(dec!(106))));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    process_event(&OrchestratorEvent::DownTriggerOffset, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Bid), state), (OrderType::StopLimit, Some(dec!(95.5)), Some(dec!(95.5))));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Buy(PriceType::Bid), state), (OrderType::MarketIfTouched, None, Some(dec!(95.5))));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Ask), state), (OrderType::LimitIfTouched, Some(dec!(105.5)), Some(dec!(105.5))));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(state.order_type(), OrderType::Limit);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__exec_inst_and_time_in_force() {
+    let state = &mut connected_state();
+    let issued = |event: OrchestratorEvent, state: &mut State| {
+        let o = issue(event, state);
+        (o.time_in_force.unwrap(), o.exec_inst.unwrap())
+    };
+    assert_eq!(issued(OrchestratorEvent::Buy(PriceType::Bid), state), (TimeInForce::GoodTillCancel, vec![]));
+    process_event(&OrchestratorEvent::TogglePostOnly, state);
+    process_event(&OrchestratorEvent::ToggleReduceOnly, state);
+    process_event(&OrchestratorEvent::RotateTimeInForce, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Ask), state), (TimeInForce::ImmediateOrCancel, vec![ExecInst::ParticipateDoNotInitiate, ExecInst::ReduceOnly]));
+
+    // post only doesn't apply to Market, trigger price and close on trigger only to conditional orders
+    process_event(&OrchestratorEvent::ToggleCloseOnTrigger, state);
+    process_event(&OrchestratorEvent::RotateTriggerPrice, state);
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Bid), state), (TimeInForce::ImmediateOrCancel, vec![ExecInst::ReduceOnly]));
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    assert_eq!(issued(OrchestratorEvent::Sell(PriceType::Bid), state), (TimeInForce::ImmediateOrCancel, vec![ExecInst::ReduceOnly, ExecInst::Close, ExecInst::MarkPrice]));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__connection_loss() {
+    let state = &mut connected_state();
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, .. order.clone() }), state);
+
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Disconnected), state);
+    assert_eq!((state.bid, state.ask), (dec!(-1), dec!(-1)));
+    assert_eq!(process_event(&OrchestratorEvent::Sell(PriceType::Ask), state), None);
+    assert_eq!(state.status, "Won't trade whilst feed is Disconnected!");
+
+    // order got filled whilst disconnected, hence missing from the snapshot
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), state);
+    process_event(&OrchestratorEvent::SyncLiveOrders(vec![]), state);
+    assert_eq!(state.live_orders().count(), 0);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__stale_feed() {
+    let state = &mut connected_state();
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Stale), state);
+    assert_eq!(process_event(&OrchestratorEvent::Buy(PriceType::Bid), state), None);
+    assert_eq!(state.status, "Won't trade whilst feed is Stale!");
+
+    // prices are retained whilst stale, and tradeable once the feed is back
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), state);
+    issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__dead_mans_switch() {
+    let state = &mut connected_state();
+    assert_eq!(process_event(&OrchestratorEvent::RefreshDeadMansSwitch, state), None);
+    assert_eq!(process_event(&OrchestratorEvent::Exit, state), None);
+
+    state.dead_mans_switch = Some(DeadMansSwitch { timeout: Duration::from_secs(60), cancel_at: None });
+    assert_eq!(process_event(&OrchestratorEvent::RefreshDeadMansSwitch, state), Some(ExchangeCmd::CancelAllAfter(Duration::from_secs(60))));
+    let cancel_at = Utc::now() + chrono::Duration::seconds(60);
+    process_event(&OrchestratorEvent::UpdateDeadMansSwitch(Some(cancel_at)), state);
+    assert_eq!(state.status, "Dead man's switch armed, 60s timeout");
+    assert_eq!(state.dead_mans_switch.as_ref().unwrap().cancel_at, Some(cancel_at));
+
+    // left to expire whilst the feed is unhealthy
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Stale), state);
+    assert_eq!(process_event(&OrchestratorEvent::RefreshDeadMansSwitch, state), None);
+    process_event(&OrchestratorEvent::UpdateDeadMansSwitch(Some(Utc::now() - chrono::Duration::seconds(1))), state);
+    assert_eq!(process_event(&OrchestratorEvent::RefreshDeadMansSwitch, state), None);
+    assert_eq!(state.status, "Dead man's switch expired, orders canceled by the exchange!");
+    assert_eq!(state.dead_mans_switch.as_ref().unwrap().cancel_at, None);
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), state);
+    assert!(process_event(&OrchestratorEvent::RefreshDeadMansSwitch, state).is_some());
+    process_event(&OrchestratorEvent::UpdateDeadMansSwitch(Some(cancel_at)), state);
+    assert_eq!(state.status, "Dead man's switch armed, 60s timeout");
+
+    // disarmed on exit
+    assert_eq!(process_event(&OrchestratorEvent::Exit, state), Some(ExchangeCmd::CancelAllAfter(Duration::ZERO)));
+    process_event(&OrchestratorEvent::UpdateDeadMansSwitch(None), state);
+    assert_eq!(state.status, "Dead man's switch disarmed");
+    assert_eq!(state.dead_mans_switch.as_ref().unwrap().cancel_at, None);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__dead_mans_switch_config() {
+    let template = fs::read_to_string("app.toml.template").unwrap();
+    let dir = env::temp_dir().join(format!("bitmex-warrior-dead-mans-switch-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_filename = dir.join("app.toml").to_str().unwrap().to_string();
+    let load = |timeout_secs: u64, refresh_secs: u64| {
+        fs::write(&config_filename, format!("{}\n[dead_mans_switch]\ntimeout_secs = {}\nrefresh_secs = {}\n", template, timeout_secs, refresh_secs)).unwrap();
+        AppConfig::new(&config_filename).map(|x| x.dead_mans_switch.map(|x| (x.timeout_secs, x.refresh_secs))).map_err(|x| x.to_string())
+    };
+    assert_eq!(load(60, 15), Ok(Some((60, 15))));
+    assert_eq!(load(60, 0), Err("dead_mans_switch: refresh_secs 0 must be positive and below timeout_secs 60".to_string()));
+    assert_eq!(load(60, 60), Err("dead_mans_switch: refresh_secs 60 must be positive and below timeout_secs 60".to_string()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__heartbeat_config() {
+    let template = fs::read_to_string("app.toml.template").unwrap();
+    let dir = env::temp_dir().join(format!("bitmex-warrior-heartbeat-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_filename = dir.join("app.toml").to_str().unwrap().to_string();
+    let load = |interval_secs: u64, timeout_secs: u64| {
+        let config = template.lines()
+            .map(|x| if x.starts_with("heartbeat_interval_secs") {
+                format!("heartbeat_interval_secs = {}", interval_secs)
+            } else if x.starts_with("heartbeat_timeout_secs") {
+                format!("heartbeat_timeout_secs = {}", timeout_secs)
+            } else {
+                x.to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        fs::write(&config_filename, config).unwrap();
+        AppConfig::new(&config_filename).map(|x| (x.heartbeat_interval_secs, x.heartbeat_timeout_secs)).map_err(|x| x.to_string())
+    };
+    assert_eq!(load(5, 10), Ok((5, 10)));
+    assert_eq!(load(0, 10), Err("heartbeat_interval_secs 0 and heartbeat_timeout_secs 10 must be positive".to_string()));
+    assert_eq!(load(5, 0), Err("heartbeat_interval_secs 5 and heartbeat_timeout_secs 0 must be positive".to_string()));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__input_qty_and_price() {
+    let state = &mut connected_state();
+    state.lot_size = dec!(5);
+
+    process_event(&OrchestratorEvent::SetQty(dec!(12)), state);
+    assert_eq!(state.qty, dec!(10));
+    assert_eq!(state.status, "Invalid qty 12, must be a positive multiple of lot size 5!");
+    process_event(&OrchestratorEvent::SetQty(dec!(250)), state);
+    assert_eq!(state.qty, dec!(250));
+
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Absolute(dec!(99.3)))), state);
+    assert_eq!(state.price_input, None);
+    assert_eq!(state.status, "Invalid price 99.3, must be a positive multiple of tick size 0.5!");
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Absolute(dec!(99.5)))), state);
+    let order_price = |state: &mut State, event: OrchestratorEvent| {
+        let o = issue(event, state);
+        (o.qty, o.price)
+    };
+    assert_eq!(order_price(state, OrchestratorEvent::Buy(PriceType::Bid)), (Some(dec!(250)), Some(dec!(99.5))));
+
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Ticks(2))), state);
+    assert_eq!(order_price(state, OrchestratorEvent::Buy(PriceType::Bid)), (Some(dec!(250)), Some(dec!(101))));
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Percent(dec!(-1)))), state);
+    assert_eq!(order_price(state, OrchestratorEvent::Sell(PriceType::Ask)), (Some(dec!(250)), Some(dec!(100))));  // 99.99, to the nearest tick
+    process_event(&OrchestratorEvent::SetPrice(None), state);
+    assert_eq!(state.status, "Price reset to touch");
+    assert_eq!(order_price(state, OrchestratorEvent::Sell(PriceType::Ask)), (Some(dec!(250)), Some(dec!(101))));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_process_events__instrument_rounding() {
+    let state = &mut connect(State::new(dec!(150), dec!(10), dec!(0.5), dec!(5.2)), dec!(51170.5), dec!(51171));
+    process_event(&OrchestratorEvent::NewInstrument(Instrument { symbol: "XBTUSD".to_string(), tick_size: dec!(0.1), lot_size: dec!(100), multiplier: dec!(-100000000), is_inverse: true, max_order_qty: Some(dec!(1000)) }), state);
+    assert_eq!((state.tick_size, state.lot_size, state.qty, state.qty_increment, state.trigger_offset), (dec!(0.1), dec!(100), dec!(200), dec!(100), dec!(5.2)));
+
+    // prices land on the tick, free of float artefacts
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Ticks(3))), state);
+    let order = issue(OrchestratorEvent::Buy(PriceType::Bid), state);
+    assert_eq!((order.qty, order.price), (Some(dec!(200)), Some(dec!(51170.8))));
+    assert_eq!(order.price.unwrap().to_string(), "51170.8");
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, .. order }), state);
+    match process_event(&OrchestratorEvent::UpLastPrice, state) {
+        Some(ExchangeCmd::AmendOrder(o)) => assert_eq!(o.price.unwrap().to_string(), "51170.9"),
+        other => panic!("Unexpected cmd: {:?}", other)
+    };
+
+    process_event(&OrchestratorEvent::SetQty(dec!(1100)), state);
+    assert_eq!(state.status, "Invalid qty 1100, exceeds max order qty 1000!");
+    state.qty = dec!(1100);
+    assert_eq!(process_event(&OrchestratorEvent::Sell(PriceType::Ask), state), None);
+    assert_eq!(state.status, "Won't trade qty 1100, above max order qty 1000!");
+    state.qty = dec!(40);
+    assert_eq!(process_event(&OrchestratorEvent::Sell(PriceType::Ask), state), None);
+    assert_eq!(state.status, "Won't trade qty 40, below lot size 100!");
+}
@@ -0,0 +1,52 @@
+use bitmex_warrior::model::{Level, Side};
+use bitmex_warrior::order_book::{OrderBook, OrderBookError};
+use bitmex_warrior::ws_model::{OrderBookL2Row, TableAction};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+#[cfg(test)]
+fn row(id: u64, side: Side, size: Option<Decimal>, price: Option<Decimal>) -> OrderBookL2Row {
+    OrderBookL2Row { symbol: "XBTUSD".to_string(), id, side, size, price }
+}
+
+#[test]
+fn test_order_book() {
+    let mut book = OrderBook::new();
+
+    // ignored till partial
+    assert_eq!(book.apply(&TableAction::Update, &[row(1, Side::Sell, Some(dec!(10)), None)]), Ok(()));
+    assert!(!book.is_synced());
+
+    book.apply(&TableAction::Partial, &[
+        row(1, Side::Sell, Some(dec!(10)), Some(dec!(101))),
+        row(2, Side::Sell, Some(dec!(20)), Some(dec!(101.5))),
+        row(3, Side::Buy, Some(dec!(30)), Some(dec!(100))),
+        row(4, Side::Buy, Some(dec!(40)), Some(dec!(99.5))),
+    ]).unwrap();
+    assert_eq!(book.best_bid(), Some(Level { price: dec!(100), size: dec!(30) }));
+    assert_eq!(book.best_ask(), Some(Level { price: dec!(101), size: dec!(10) }));
+
+    book.apply(&TableAction::Update, &[row(1, Side::Sell, Some(dec!(5)), None)]).unwrap();
+    book.apply(&TableAction::Delete, &[row(3, Side::Buy, None, None)]).unwrap();
+    book.apply(&TableAction::Insert, &[row(5, Side::Buy, Some(dec!(50)), Some(dec!(100.5)))]).unwrap();
+    assert_eq!(book.depth(Side::Buy, 5), vec![Level { price: dec!(100.5), size: dec!(50) }, Level { price: dec!(99.5), size: dec!(40) }]);
+    assert_eq!(book.depth(Side::Sell, 1), vec![Level { price: dec!(101), size: dec!(5) }]);
+    assert_eq!(book.cum_size(Side::Sell, 2), dec!(25));
+}
+
+#[test]
+fn test_order_book_inconsistencies() {
+    let mut book = OrderBook::new();
+    let partial = [row(1, Side::Sell, Some(dec!(10)), Some(dec!(101))), row(2, Side::Buy, Some(dec!(30)), Some(dec!(100)))];
+
+    book.apply(&TableAction::Partial, &partial).unwrap();
+    assert_eq!(book.apply(&TableAction::Update, &[row(3, Side::Buy, Some(dec!(1)), None)]), Err(OrderBookError::UnknownLevel(3)));
+    assert!(!book.is_synced());
+    assert_eq!(book.best_bid(), None);
+
+    book.apply(&TableAction::Partial, &partial).unwrap();
+    assert_eq!(book.apply(&TableAction::Insert, &[row(2, Side::Buy, Some(dec!(1)), Some(dec!(100)))]), Err(OrderBookError::DuplicateLevel(2)));
+
+    book.apply(&TableAction::Partial, &partial).unwrap();
+    assert_eq!(book.apply(&TableAction::Insert, &[row(3, Side::Buy, Some(dec!(1)), Some(dec!(101)))]), Err(OrderBookError::CrossedBook(dec!(101), dec!(101))));
+}
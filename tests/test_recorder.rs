@@ -0,0 +1,39 @@
+use std::env;
+use std::fs;
+
+use chrono::{TimeZone, Utc};
+use uuid::Uuid;
+
+use bitmex_warrior::recorder::{read_recording, recording_files, RecordedFrame, Recorder, RecordingConfig};
+
+fn record_and_read_back(compress: bool) {
+    let dir = env::temp_dir().join(format!("bitmex-warrior-recording-{}", Uuid::new_v4()));
+    let config = RecordingConfig { dir: dir.to_str().unwrap().to_string(), max_file_bytes: 200, compress };
+    let frames = (0..10).map(|i| RecordedFrame { received_at: Utc.timestamp_millis(1_600_000_000_000 + i), frame: format!(r#"{{"table":"trade","action":"insert","data":[{{"seq":{}}}]}}"#, i) }).collect::<Vec<_>>();
+    let mut recorder = Recorder::new(config).unwrap();
+    for frame in frames.iter() {
+        recorder.record(frame.received_at, &frame.frame).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    // rotated into multiple files, which read back in order
+    let files = recording_files(&dir).unwrap();
+    assert!(files.len() > 1);
+    assert!(files.iter().all(|x| x.to_str().unwrap().ends_with(if compress { ".jsonl.gz" } else { ".jsonl" })));
+    let read_back = files.iter().flat_map(|x| read_recording(x).unwrap()).collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(read_back, frames);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_recorder__plain() {
+    record_and_read_back(false);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_recorder__compressed() {
+    record_and_read_back(true);
+}
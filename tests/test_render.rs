@@ -1,17 +1,101 @@
+use std::time::Duration;
+
 use bitmex_warrior::model::*;
 use bitmex_warrior::render::render_state;
+use rust_decimal_macros::dec;
 
 #[cfg(test)]
-
 #[test]
 fn test_render_state() {
-    let state = &mut State::new(10.0, 1.0);
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let rendered = render_state("HELLO!", state);
+    let expected = "HELLO!\r
+\r
+FEED: Connecting\r
+BID: -1.0 / ASK: -1.0\r
+QTY: 10 / PRICE: touch\r
+ORDER TYPE: Limit\r
+TRIGGER OFFSET: 5.0 / TRIGGER PRICE: LastPrice\r
+TIME IN FORCE: GoodTillCancel\r
+POST ONLY: off / REDUCE ONLY: off / CLOSE ON TRIGGER: off\r
+STATUS: ";
+    assert_eq!(rendered, expected);
+}
+#[test]
+fn test_render_state_with_position_and_orders() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.position = Some(Position { current_qty: Some(dec!(100)), avg_entry_price: Some(dec!(51124.5)), unrealised_pnl: Some(dec!(-3910)), ..Position::default() });
+    state.add_order(ExchangeOrder { cl_ord_id: "1".to_string(), ord_status: OrderStatus::PartiallyFilled, ord_type: Some(OrderType::Limit), price: Some(dec!(100)), qty: Some(dec!(10)), side: Some(Side::Buy), cum_qty: Some(dec!(4)), leaves_qty: Some(dec!(6)), avg_px: Some(dec!(99.5)), stop_px: None, time_in_force: None, exec_inst: None });
+    state.add_order(ExchangeOrder { cl_ord_id: "2".to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(OrderType::Limit), price: Some(dec!(101)), qty: Some(dec!(20)), side: Some(Side::Sell), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None });
     let rendered = render_state("HELLO!", state);
-    let expected = format!("HELLO!\r
+    let expected = "HELLO!\r
 \r
-BID: -1.00 / ASK: -1.00\r
-QTY: 10.00\r
+FEED: Connecting\r
+BID: -1.0 / ASK: -1.0\r
+QTY: 10 / PRICE: touch\r
 ORDER TYPE: Limit\r
-STATUS: ");
+TRIGGER OFFSET: 5.0 / TRIGGER PRICE: LastPrice\r
+TIME IN FORCE: GoodTillCancel\r
+POST ONLY: off / REDUCE ONLY: off / CLOSE ON TRIGGER: off\r
+STATUS: \r
+POSITION: 100 @ 51124.5 LIQ: - UPNL: -3910 RPNL: - LEV: -\r
+ORDER 1: Buy Limit PartiallyFilled 10 @ 100.0 filled 4/10 @ avg 99.5\r
+ORDER 2: Sell Limit NotYetIssued 20 @ 101.0";
     assert_eq!(rendered, expected);
-}
\ No newline at end of file
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_render_state__prices_to_tick_scale() {
+    let state = &mut State::new(dec!(0.0001), dec!(0.0001), dec!(0.00000001), dec!(0.00000010));
+    state.add_order(ExchangeOrder { cl_ord_id: "1".to_string(), ord_status: OrderStatus::New, ord_type: Some(OrderType::StopLimit), price: Some(dec!(0.00001235)), qty: Some(dec!(0.0001)), side: Some(Side::Sell), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: Some(dec!(0.0000124)), time_in_force: None, exec_inst: None });
+    let rendered = render_state("HELLO!", state);
+    assert!(rendered.contains("BID: -1.00000000 / ASK: -1.00000000\r\nQTY: 0.0001 / PRICE: touch"), "{}", rendered);
+    assert!(rendered.contains("TRIGGER OFFSET: 0.00000010 /"), "{}", rendered);
+    assert!(rendered.ends_with("ORDER 1: Sell StopLimit New 0.0001 @ 0.00001235 trigger 0.00001240"), "{}", rendered);
+}
+
+#[test]
+fn test_render_state_with_ladder() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.bids = vec![Level { price: dec!(100), size: dec!(30) }, Level { price: dec!(99.5), size: dec!(40) }];
+    state.asks = vec![Level { price: dec!(101), size: dec!(10) }];
+    state.add_order(ExchangeOrder { cl_ord_id: "1".to_string(), ord_status: OrderStatus::New, ord_type: Some(OrderType::Limit), price: Some(dec!(100.5)), qty: Some(dec!(10)), side: Some(Side::Buy), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None });
+    state.add_trade(Trade { timestamp: "2020-01-01T00:00:00Z".parse().unwrap(), side: Side::Sell, size: dec!(5), price: dec!(100), tick_direction: TickDirection::MinusTick });
+    state.add_trade(Trade { timestamp: "2020-01-01T00:00:01Z".parse().unwrap(), side: Side::Sell, size: dec!(2), price: dec!(100), tick_direction: TickDirection::ZeroMinusTick });
+    let rendered = render_state("HELLO!", state);
+    let expected_ladder = "\r
+\r
+      ORDERS |        BID |      PRICE |        ASK |     TRADES\r
+             |            |      101.0 |         10 |           \r
+        B 10 |            |      100.5 |            |           \r
+             |         30 |      100.0 |            |          7\r
+             |         40 |       99.5 |            |           ";
+    assert!(rendered.contains(expected_ladder), "{}", rendered);
+}
+
+#[test]
+fn test_render_state_with_tape() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.tape_size = 2;
+    state.large_trade_size = dec!(1000);
+    state.add_trade(Trade { timestamp: "2020-01-01T00:00:00Z".parse().unwrap(), side: Side::Buy, size: dec!(1), price: dec!(100), tick_direction: TickDirection::PlusTick });
+    state.add_trade(Trade { timestamp: "2020-01-01T00:00:01Z".parse().unwrap(), side: Side::Buy, size: dec!(5000), price: dec!(100.5), tick_direction: TickDirection::PlusTick });
+    state.add_trade(Trade { timestamp: "2020-01-01T00:00:02.5Z".parse().unwrap(), side: Side::Sell, size: dec!(20), price: dec!(100), tick_direction: TickDirection::MinusTick });
+    let rendered = render_state("HELLO!", state);
+    let expected_tape = format!("\r
+\r
+TAPE:\r
+00:00:02.500 Sell         20 @      100.0 - \r
+{}00:00:01.000 Buy        5000 @      100.5 + {}", termion::style::Invert, termion::style::Reset);
+    assert!(rendered.ends_with(&expected_tape), "{}", rendered);
+}
+
+#[test]
+fn test_render_state_with_dead_mans_switch() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    state.dead_mans_switch = Some(DeadMansSwitch { timeout: Duration::from_secs(60), cancel_at: None });
+    assert!(render_state("HELLO!", state).ends_with("STATUS: \r\nDEAD MAN'S SWITCH: 60s / disarmed"));
+    state.dead_mans_switch = Some(DeadMansSwitch { timeout: Duration::from_secs(60), cancel_at: Some("2020-01-01T12:34:56Z".parse().unwrap()) });
+    assert!(render_state("HELLO!", state).ends_with("STATUS: \r\nDEAD MAN'S SWITCH: 60s / armed till 12:34:56"));
+}
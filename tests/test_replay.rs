@@ -0,0 +1,78 @@
+use std::io;
+use std::sync::mpsc;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use bitmex_warrior::model::*;
+use bitmex_warrior::recorder::RecordedFrame;
+use bitmex_warrior::replay::{self, Replay, ReplayControl, ReplaySpeed, ReplayStep};
+use rust_decimal_macros::dec;
+
+fn at(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis(1_600_000_000_000 + millis)
+}
+
+fn frames() -> Vec<io::Result<RecordedFrame>> {
+    vec![
+        Ok(RecordedFrame { received_at: at(0), frame: r#"{"table":"orderBook10","action":"partial","data":[{"symbol":"XBTUSD","bids":[[100,10]],"asks":[[100.5,30]],"timestamp":"2020-01-01T00:00:00Z"}]}"#.to_string() }),
+        Ok(RecordedFrame { received_at: at(10), frame: "pong".to_string() }),
+        Ok(RecordedFrame { received_at: at(20), frame: r#"{"table":"orderBook10","action":"update","data":[{"symbol":"XBTUSD","bids":[[99.5,10]],"asks":[[100,30]],"timestamp":"2020-01-01T00:00:00Z"}]}"#.to_string() }),
+    ]
+}
+
+fn script() -> Vec<(DateTime<Utc>, OrchestratorEvent)> {
+    vec![(at(5), OrchestratorEvent::Buy(PriceType::Bid)), (at(-1), OrchestratorEvent::NewStatus("replay".to_string()))]
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_replay__steps() {
+    let steps = Replay::new(frames().into_iter(), script()).collect::<io::Result<Vec<_>>>().unwrap();
+    assert_eq!(steps.iter().map(|x| x.at).collect::<Vec<_>>(), vec![at(-1), at(0), at(5), at(10), at(20)]);
+    assert_eq!(steps[1], ReplayStep { at: at(0), events: vec![
+        OrchestratorEvent::NewAsk(dec!(100.5)),
+        OrchestratorEvent::NewBid(dec!(100)),
+        OrchestratorEvent::NewDepth(vec![Level { price: dec!(100), size: dec!(10) }], vec![Level { price: dec!(100.5), size: dec!(30) }])] });
+    assert!(steps[3].events.is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_replay__run() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (_control_tx, control_rx) = mpsc::channel();
+    let mut issued = Vec::new();
+    let mut refreshes = 0;
+    replay::run(Replay::new(frames().into_iter(), script()), state, ReplaySpeed::Max, &control_rx,
+        |cmd| if let ExchangeCmd::IssueOrder(order) = cmd { issued.push((order.side, order.price)) },
+        |_| refreshes += 1).unwrap();
+    assert_eq!(issued, vec![(Some(Side::Buy), Some(dec!(100)))]);
+    assert_eq!((state.bid, state.ask), (dec!(99.5), dec!(100)));
+    assert!(refreshes > 0);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_replay__pause_and_step() {
+    let state = &mut State::new(dec!(10), dec!(1), dec!(0.5), dec!(5));
+    let (control_tx, control_rx) = mpsc::channel();
+    for control in [ReplayControl::SetSpeed(ReplaySpeed::Multiple(1000.0)), ReplayControl::TogglePause, ReplayControl::Step, ReplayControl::Step] {  // status, depth
+        control_tx.send(control).unwrap();
+    }
+    drop(control_tx);  // stops the replay once paused without further controls
+    replay::run(Replay::new(frames().into_iter(), script()), state, ReplaySpeed::RealTime, &control_rx, |_| (), |_| ()).unwrap();
+    assert_eq!(state.connection, ConnectionState::Connected);  // sans scripted connection
+    assert_eq!((state.bid, state.ask), (dec!(100), dec!(100.5)));
+    assert!(state.orders.is_empty());
+
+    assert_eq!(ReplaySpeed::parse("10x"), Some(ReplaySpeed::Multiple(10.0)));
+    assert_eq!(ReplaySpeed::parse("max"), Some(ReplaySpeed::Max));
+    assert_eq!(ReplaySpeed::parse("0x"), None);
+
+    assert_eq!(ReplaySpeed::RealTime.faster(), ReplaySpeed::Multiple(2.0));
+    assert_eq!(ReplaySpeed::Multiple(2.0).slower(), ReplaySpeed::RealTime);
+    assert_eq!(ReplaySpeed::RealTime.slower(), ReplaySpeed::Multiple(0.5));
+    assert_eq!(ReplaySpeed::Max.faster(), ReplaySpeed::Max);
+    assert_eq!(ReplaySpeed::Max.slower(), ReplaySpeed::RealTime);
+}
@@ -0,0 +1,227 @@
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use bitmex_warrior::model::*;
+use bitmex_warrior::orchestrator::process_event;
+use bitmex_warrior::risk::{RiskEngine, RiskLimits, SessionPnl, MAX_EXEC_IDS};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+fn connected_state(limits: RiskLimits) -> State {
+    let mut state = State::new(dec!(100), dec!(100), dec!(0.5), dec!(5));
+    state.risk = RiskEngine::new(limits);
+    process_event(&OrchestratorEvent::NewConnectionState(ConnectionState::Connected), &mut state);
+    process_event(&OrchestratorEvent::NewBid(dec!(100)), &mut state);
+    process_event(&OrchestratorEvent::NewAsk(dec!(101)), &mut state);
+    state
+}
+
+fn is_issued(event: OrchestratorEvent, state: &mut State) -> bool {
+    matches!(process_event(&event, state), Some(ExchangeCmd::IssueOrder(_)))
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__max_order_qty_and_notional() {
+    let state = &mut connected_state(RiskLimits { max_order_qty: Some(dec!(500)), max_notional: Some(dec!(40000)), ..RiskLimits::default() });
+    state.qty = dec!(600);
+    assert!(!is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert_eq!(state.status, "Risk rejected buy of 600 @ 100: qty above max order qty 500!");
+    assert_eq!(state.live_orders().count(), 0);
+
+    state.qty = dec!(500);
+    assert!(!is_issued(OrchestratorEvent::Sell(PriceType::Ask), state));
+    assert_eq!(state.status, "Risk rejected sell of 500 @ 101: notional 50500 above max notional 40000!");
+
+    // inverse contracts' qty is the notional
+    state.instrument = Some(Instrument { symbol: "XBTUSD".to_string(), tick_size: dec!(0.5), lot_size: dec!(100), multiplier: dec!(-100000000), is_inverse: true, max_order_qty: None });
+    assert!(is_issued(OrchestratorEvent::Sell(PriceType::Ask), state));
+    assert_eq!(state.live_orders().count(), 1);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__max_position() {
+    let state = &mut connected_state(RiskLimits { max_position: Some(dec!(300)), ..RiskLimits::default() });
+    process_event(&OrchestratorEvent::UpdatePosition(Position { current_qty: Some(dec!(100)), ..Position::default() }), state);
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    // working buys count towards the position, as if filled
+    assert!(!is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert_eq!(state.status, "Risk rejected buy of 100 @ 100: resulting position 400 above max position 300!");
+
+    // reducing the position is always allowed
+    process_event(&OrchestratorEvent::UpdatePosition(Position { current_qty: Some(dec!(-1000)), ..Position::default() }), state);
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert!(!is_issued(OrchestratorEvent::Sell(PriceType::Ask), state));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__price_band() {
+    let state = &mut connected_state(RiskLimits { price_band_pct: Some(dec!(1)), ..RiskLimits::default() });
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Absolute(dec!(99)))), state);
+    assert!(!is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert_eq!(state.status, "Risk rejected buy of 100 @ 99: price more than 1% from mid 100.5!");
+
+    process_event(&OrchestratorEvent::SetPrice(Some(PriceInput::Ticks(-1))), state);
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    let order = state.last_order().unwrap().clone();
+    assert_eq!(order.price, Some(dec!(99.5)));
+
+    // amends are banded too, leaving the order intact
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, ..order.clone() }), state);
+    assert_eq!(process_event(&OrchestratorEvent::DownLastPrice, state), None);
+    assert_eq!(state.status, format!("Risk rejected amend of {} to 99: price more than 1% from mid 100.5!", order.cl_ord_id));
+    assert_eq!(state.orders[&order.cl_ord_id].price, Some(dec!(99.5)));
+    assert!(matches!(process_event(&OrchestratorEvent::UpLastPrice, state), Some(ExchangeCmd::AmendOrder(_))));
+
+    // market orders have no limit price to band
+    process_event(&OrchestratorEvent::RotateOrderType, state);
+    process_event(&OrchestratorEvent::SetPrice(None), state);
+    assert_eq!(state.order_type(), OrderType::Market);
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Ask), state));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__order_rate() {
+    let state = &mut connected_state(RiskLimits::default());
+    let order = ExchangeOrder { cl_ord_id: "xxx".to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(OrderType::Limit), price: Some(dec!(100)), qty: Some(dec!(100)), side: Some(Side::Buy), cum_qty: None, leaves_qty: None, avg_px: None, stop_px: None, time_in_force: None, exec_inst: None };
+    let mut risk = RiskEngine::new(RiskLimits { max_orders_per_sec: Some(2), ..RiskLimits::default() });
+    let t0 = Instant::now();
+    risk.on_sent(t0);
+    risk.on_sent(t0 + Duration::from_millis(500));
+    assert_eq!(risk.check_order(&order, state, t0 + Duration::from_millis(900)), Err("Risk rejected buy of 100 @ 100: above max 2 orders/sec!".to_string()));
+    assert_eq!(risk.check_amend("xxx", dec!(100.5), state, t0 + Duration::from_millis(900)), Err("Risk rejected amend of xxx to 100.5: above max 2 orders/sec!".to_string()));
+    assert_eq!(risk.check_order(&order, state, t0 + Duration::from_millis(1000)), Ok(()));
+
+    // via the orchestrator, the 3rd order in quick succession is rejected
+    state.risk = RiskEngine::new(RiskLimits { max_orders_per_sec: Some(2), ..RiskLimits::default() });
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert!(is_issued(OrchestratorEvent::Sell(PriceType::Ask), state));
+    assert!(!is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert_eq!(state.live_orders().count(), 2);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__app_config() {
+    let template = fs::read_to_string("app.toml.template").unwrap();
+    let dir = env::temp_dir().join(format!("bitmex-warrior-risk-{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_filename = dir.join("app.toml").to_str().unwrap().to_string();
+    fs::write(&config_filename, format!("{}\n[risk]\nmax_order_qty = 500\nprice_band_pct = 0.5\n", template)).unwrap();
+    let cfg = AppConfig::new(&config_filename).unwrap();
+    assert_eq!(cfg.risk, Some(RiskLimits { max_order_qty: Some(dec!(500)), price_band_pct: Some(dec!(0.5)), ..RiskLimits::default() }));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__session_pnl() {
+    let mut pnl = SessionPnl::default();
+    pnl.on_fill(Side::Buy, dec!(100), dec!(100), None);
+    pnl.on_fill(Side::Sell, dec!(50), dec!(110), None);
+    assert_eq!((pnl.position_qty, pnl.realised_pnl, pnl.unrealised_pnl(dec!(120), None)), (dec!(50), dec!(500), dec!(1000)));
+    pnl.on_fill(Side::Sell, dec!(100), dec!(90), None);  // flips short
+    assert_eq!((pnl.position_qty, pnl.realised_pnl, pnl.unrealised_pnl(dec!(80), None)), (dec!(-50), dec!(0), dec!(500)));
+
+    // linear contracts' PnL scales with the multiplier, eg. XBt per USD for ETHUSD
+    let ethusd = Instrument { symbol: "ETHUSD".to_string(), tick_size: dec!(0.05), lot_size: dec!(1), multiplier: dec!(100), is_inverse: false, max_order_qty: None };
+    let mut pnl = SessionPnl::default();
+    pnl.on_fill(Side::Sell, dec!(10), dec!(3000), Some(&ethusd));
+    assert_eq!(pnl.unrealised_pnl(dec!(2990), Some(&ethusd)), dec!(10000));
+    pnl.on_fill(Side::Buy, dec!(10), dec!(3010), Some(&ethusd));
+    assert_eq!((pnl.position_qty, pnl.realised_pnl), (dec!(0), dec!(-10000)));
+
+    // inverse contracts' PnL is in the base currency, ie. qty * (1/entry - 1/exit), regardless of the multiplier
+    let xbtusd = Instrument { symbol: "XBTUSD".to_string(), tick_size: dec!(0.5), lot_size: dec!(100), multiplier: dec!(-100000000), is_inverse: true, max_order_qty: None };
+    let mut pnl = SessionPnl::default();
+    pnl.on_fill(Side::Buy, dec!(100), dec!(50000), Some(&xbtusd));
+    assert_eq!(pnl.unrealised_pnl(dec!(40000), Some(&xbtusd)), dec!(-0.0005));
+    pnl.on_fill(Side::Sell, dec!(100), dec!(40000), Some(&xbtusd));
+    assert_eq!((pnl.position_qty, pnl.realised_pnl), (dec!(0), dec!(-0.0005)));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__redelivered_fills() {
+    let mut risk = RiskEngine::default();
+    let fill = |exec_id: usize| Fill { cl_ord_id: "o1".to_string(), exec_id: exec_id.to_string(), side: Side::Buy, last_qty: dec!(1), last_px: dec!(100), cum_qty: None };
+    risk.on_fill(&fill(0), None);
+    risk.on_fill(&fill(0), None);
+    assert_eq!(risk.pnl.position_qty, dec!(1));
+
+    // only the most recent exec ids are retained
+    for exec_id in 1..=MAX_EXEC_IDS {
+        risk.on_fill(&fill(exec_id), None);
+        risk.on_fill(&fill(exec_id), None);
+    }
+    assert_eq!(risk.pnl.position_qty, Decimal::from(MAX_EXEC_IDS + 1));
+    risk.on_fill(&fill(1), None);
+    assert_eq!(risk.pnl.position_qty, Decimal::from(MAX_EXEC_IDS + 1));
+    risk.on_fill(&fill(0), None);
+    assert_eq!(risk.pnl.position_qty, Decimal::from(MAX_EXEC_IDS + 2));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__kill_switch() {
+    let state = &mut connected_state(RiskLimits { max_session_loss: Some(dec!(50)), flatten_on_kill: true, ..RiskLimits::default() });
+    let filled = match process_event(&OrchestratorEvent::Buy(PriceType::Bid), state) {
+        Some(ExchangeCmd::IssueOrder(o)) => o,
+        other => panic!("Unexpected cmd: {:?}", other)
+    };
+    process_event(&OrchestratorEvent::UpdateOrder(ExchangeOrder { ord_status: OrderStatus::New, ..filled.clone() }), state);
+    process_event(&OrchestratorEvent::NewFill(Fill { cl_ord_id: filled.cl_ord_id.clone(), exec_id: "1".to_string(), side: Side::Buy, last_qty: dec!(100), last_px: dec!(100), cum_qty: Some(dec!(100)) }), state);
+    assert_eq!(state.session_pnl(), dec!(50));  // marked to mid
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    let working = state.last_order().unwrap().clone();
+
+    // within the limit, marked to the mark price once known
+    assert_eq!(process_event(&OrchestratorEvent::UpdatePosition(Position { current_qty: Some(dec!(100)), mark_price: Some(dec!(99.6)), ..Position::default() }), state), None);
+    assert_eq!(state.session_pnl(), dec!(-40));
+    // all orders of the symbol canceled, not just those tracked
+    let flatten = match process_event(&OrchestratorEvent::UpdatePosition(Position { mark_price: Some(dec!(99.5)), ..Position::default() }), state) {
+        Some(ExchangeCmd::Batch(cmds)) => match cmds.as_slice() {
+            [ExchangeCmd::CancelAllOrders, ExchangeCmd::IssueOrder(o)] => o.clone(),
+            other => panic!("Unexpected cmds: {:?}", other)
+        },
+        other => panic!("Unexpected cmd: {:?}", other)
+    };
+    assert_eq!((flatten.side, flatten.ord_type, flatten.qty, flatten.exec_inst), (Some(Side::Sell), Some(OrderType::Market), Some(dec!(100)), Some(vec!(ExecInst::ReduceOnly))));
+    assert_eq!(state.live_orders().map(|x| &x.cl_ord_id).collect::<Vec<_>>(), vec!(&working.cl_ord_id));  // till the cancel is acknowledged
+    assert!(state.status.starts_with("Kill switch engaged at session PnL -50, canceling all orders, flattening via sell 100"));
+    assert!(state.risk.is_locked);
+
+    // locked out, without re-engaging
+    assert!(!is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    assert_eq!(state.status, "Won't trade whilst the kill switch is engaged, unlock to resume!");
+    assert_eq!(process_event(&OrchestratorEvent::UpdatePosition(Position { mark_price: Some(dec!(99)), ..Position::default() }), state), None);
+
+    // the untracked flatten order's fill closes the session position
+    process_event(&OrchestratorEvent::NewFill(Fill { cl_ord_id: flatten.cl_ord_id.clone(), exec_id: "2".to_string(), side: Side::Sell, last_qty: dec!(100), last_px: dec!(99), cum_qty: Some(dec!(100)) }), state);
+    assert_eq!((state.risk.pnl.position_qty, state.risk.pnl.realised_pnl), (dec!(0), dec!(-100)));
+
+    process_event(&OrchestratorEvent::Unlock, state);
+    assert_eq!(state.status, "Kill switch unlocked, at session PnL -100, loss limit applies afresh");
+    assert_eq!(state.session_pnl(), dec!(0));
+    assert!(is_issued(OrchestratorEvent::Buy(PriceType::Bid), state));
+    process_event(&OrchestratorEvent::Unlock, state);
+    assert_eq!(state.status, "Kill switch not engaged, ignoring unlock!");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_risk__kill_switch_sans_flatten() {
+    let state = &mut connected_state(RiskLimits { max_session_loss: Some(dec!(50)), ..RiskLimits::default() });
+    process_event(&OrchestratorEvent::NewFill(Fill { cl_ord_id: "external".to_string(), exec_id: "1".to_string(), side: Side::Sell, last_qty: dec!(100), last_px: dec!(100.5), cum_qty: Some(dec!(100)) }), state);
+    assert_eq!(process_event(&OrchestratorEvent::NewAsk(dec!(101.5)), state), None);
+    assert_eq!(process_event(&OrchestratorEvent::NewBid(dec!(101)), state), Some(ExchangeCmd::CancelAllOrders));
+    assert!(state.risk.is_locked);
+}
@@ -0,0 +1,125 @@
+use chrono::Utc;
+
+use bitmex_warrior::model::*;
+use bitmex_warrior::sim::SimExchange;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+fn order(cl_ord_id: &str, side: Side, ord_type: OrderType, price: Option<Decimal>, stop_px: Option<Decimal>, qty: Decimal) -> ExchangeOrder {
+    ExchangeOrder { cl_ord_id: cl_ord_id.to_string(), ord_status: OrderStatus::NotYetIssued, ord_type: Some(ord_type), price, qty: Some(qty), side: Some(side), cum_qty: None, leaves_qty: None, avg_px: None, stop_px, time_in_force: None, exec_inst: None }
+}
+
+fn trade(side: Side, size: Decimal, price: Decimal) -> OrchestratorEvent {
+    OrchestratorEvent::NewTrades(vec![Trade { timestamp: Utc::now(), side, size, price, tick_direction: TickDirection::ZeroPlusTick }])
+}
+
+fn depth() -> OrchestratorEvent {
+    OrchestratorEvent::NewDepth(
+        vec![Level { price: dec!(100), size: dec!(50) }, Level { price: dec!(99.5), size: dec!(100) }],
+        vec![Level { price: dec!(101), size: dec!(30) }, Level { price: dec!(101.5), size: dec!(40) }])
+}
+
+fn statuses(events: &[OrchestratorEvent]) -> Vec<(String, OrderStatus, Decimal)> {
+    events.iter().filter_map(|x| match x {
+        OrchestratorEvent::UpdateOrder(o) => Some((o.cl_ord_id.clone(), o.ord_status, o.cum_qty.unwrap_or(dec!(0)))),
+        _ => None
+    }).collect()
+}
+
+fn fills(events: &[OrchestratorEvent]) -> Vec<(Decimal, Decimal)> {
+    events.iter().filter_map(|x| match x {
+        OrchestratorEvent::NewFill(f) => Some((f.last_qty, f.last_px)),
+        _ => None
+    }).collect()
+}
+
+#[cfg(test)]
+#[test]
+#[allow(non_snake_case)]
+fn test_sim__market_walks_book() {
+    let sim = &mut SimExchange::new();
+    sim.on_market_event(&depth());
+    let events = sim.handle_cmd(&ExchangeCmd::IssueOrder(order("m1", Side::Buy, OrderType::Market, None, None, dec!(50))));
+    assert_eq!(fills(&events), vec![(dec!(30), dec!(101)), (dec!(20), dec!(101.5))]);
+    assert_eq!(statuses(&events), vec![("m1".to_string(), OrderStatus::New, dec!(0)), ("m1".to_string(), OrderStatus::PartiallyFilled, dec!(30)), ("m1".to_string(), OrderStatus::Filled, dec!(50))]);
+    match events.last() {
+        Some(OrchestratorEvent::UpdatePosition(p)) => {
+            assert_eq!(p.current_qty, Some(dec!(50)));
+            assert_eq!(p.avg_entry_price, Some(dec!(101.2)));
+        }
+        other => panic!("Unexpected event: {:?}", other)
+    }
+
+    // beyond the book, remainder fills at the last level
+    let events = sim.handle_cmd(&ExchangeCmd::IssueOrder(order("m2", Side::Sell, OrderType::Market, None, None, dec!(200))));
+    assert_eq!(fills(&events), vec![(dec!(50), dec!(100)), (dec!(100), dec!(99.5)), (dec!(50), dec!(99.5))]);
+    match events.last() {
+        Some(OrchestratorEvent::UpdatePosition(p)) => {
+            assert_eq!(p.current_qty, Some(dec!(-150)));
+            assert_eq!(p.avg_entry_price, Some(dec!(99.5)));
+            assert_eq!(p.realised_pnl, Some(dec!(50) * (dec!(100) - dec!(101.2))));
+        }
+        other => panic!("Unexpected event: {:?}", other)
+    }
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_sim__limit_queue_position() {
+    let sim = &mut SimExchange::new();
+    sim.on_market_event(&depth());
+    let events = sim.handle_cmd(&ExchangeCmd::IssueOrder(order("l1", Side::Buy, OrderType::Limit, Some(dec!(100)), None, dec!(20))));
+    assert_eq!(statuses(&events), vec![("l1".to_string(), OrderStatus::New, dec!(0))]);
+
+    // 50 ahead, level shrinks to 40 on cancels ahead of us
+    sim.on_market_event(&OrchestratorEvent::NewDepth(vec![Level { price: dec!(100), size: dec!(60) }], vec![Level { price: dec!(101), size: dec!(30) }]));
+    sim.on_market_event(&OrchestratorEvent::NewDepth(vec![Level { price: dec!(100), size: dec!(40) }], vec![Level { price: dec!(101), size: dec!(30) }]));
+    assert!(sim.on_market_event(&trade(Side::Sell, dec!(30), dec!(100))).is_empty());
+    // buys don't trade against bids
+    assert!(sim.on_market_event(&trade(Side::Buy, dec!(30), dec!(100))).is_empty());
+    let events = sim.on_market_event(&trade(Side::Sell, dec!(15), dec!(100)));
+    assert_eq!(fills(&events), vec![(dec!(5), dec!(100))]);
+    assert_eq!(statuses(&events), vec![("l1".to_string(), OrderStatus::PartiallyFilled, dec!(5))]);
+
+    // traded through fills the rest at our price
+    let events = sim.on_market_event(&trade(Side::Sell, dec!(1), dec!(99.5)));
+    assert_eq!(fills(&events), vec![(dec!(15), dec!(100))]);
+    assert_eq!(statuses(&events), vec![("l1".to_string(), OrderStatus::Filled, dec!(20))]);
+    assert!(sim.on_market_event(&trade(Side::Sell, dec!(100), dec!(99))).is_empty());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_sim__marketable_limit_and_post_only() {
+    let sim = &mut SimExchange::new();
+    sim.on_market_event(&depth());
+    let events = sim.handle_cmd(&ExchangeCmd::IssueOrder(order("l1", Side::Sell, OrderType::Limit, Some(dec!(100)), None, dec!(80))));
+    assert_eq!(fills(&events), vec![(dec!(50), dec!(100))]);
+    assert_eq!(statuses(&events).last(), Some(&("l1".to_string(), OrderStatus::PartiallyFilled, dec!(50))));
+
+    // remainder rests at the top of the book, filling when quoted through
+    let events = sim.on_market_event(&OrchestratorEvent::NewDepth(vec![Level { price: dec!(100.5), size: dec!(10) }], vec![Level { price: dec!(101), size: dec!(30) }]));
+    assert_eq!(fills(&events), vec![(dec!(30), dec!(100))]);
+
+    let post_only = ExchangeOrder { exec_inst: Some(vec![ExecInst::ParticipateDoNotInitiate]), .. order("p1", Side::Buy, OrderType::Limit, Some(dec!(101)), None, dec!(10)) };
+    let events = sim.handle_cmd(&ExchangeCmd::IssueOrder(post_only));
+    assert!(fills(&events).is_empty());
+    assert_eq!(statuses(&events).last(), Some(&("p1".to_string(), OrderStatus::Canceled, dec!(0))));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_sim__stop_trigger_and_cancel() {
+    let sim = &mut SimExchange::new();
+    sim.on_market_event(&depth());
+    sim.handle_cmd(&ExchangeCmd::IssueOrder(order("s1", Side::Buy, OrderType::Stop, None, Some(dec!(102)), dec!(10))));
+    sim.handle_cmd(&ExchangeCmd::IssueOrder(order("l1", Side::Buy, OrderType::Limit, Some(dec!(99)), None, dec!(10))));
+    assert!(sim.on_market_event(&trade(Side::Buy, dec!(1), dec!(101.5))).is_empty());
+    let events = sim.on_market_event(&trade(Side::Buy, dec!(1), dec!(102)));
+    assert_eq!(fills(&events), vec![(dec!(10), dec!(101))]);
+    assert_eq!(statuses(&events).last(), Some(&("s1".to_string(), OrderStatus::Filled, dec!(10))));
+
+    let events = sim.handle_cmd(&ExchangeCmd::CancelOrders(vec!["l1", "s1"]));
+    assert_eq!(statuses(&events), vec![("l1".to_string(), OrderStatus::Canceled, dec!(0))]);
+    assert!(matches!(events.last(), Some(OrchestratorEvent::NewStatus(_))));
+}